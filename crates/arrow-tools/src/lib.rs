@@ -15,23 +15,55 @@ pub mod seekable_reader {
         buffered_bytes: usize,
         pos: usize,     // current position in the buffer
         seekable: bool, // whether seek is still possible
+        read_ahead: usize,
+        ahead: Vec<u8>, // bytes read ahead after the buffer
+        ahead_len: usize,
+        ahead_pos: usize,
     }
 
     impl SeekRead for fs::File {}
-    impl SeekRead for SeekableReader<fs::File> {}
+    impl<R: io::Read> SeekRead for SeekableReader<R> {}
 
     const BUFFER_SIZE: usize = 8192;
     impl<R: std::io::Read> SeekableReader<R> {
         pub fn from_unbuffered_reader(reader: R, lines_to_buffer: Option<usize>) -> Self {
+            Self::from_unbuffered_reader_with_byte_limit(reader, lines_to_buffer, None)
+        }
+
+        /// Like `from_unbuffered_reader` but also stops buffering once `bytes_to_buffer` bytes
+        /// have been read, whichever limit is reached first.
+        pub fn from_unbuffered_reader_with_byte_limit(
+            reader: R,
+            lines_to_buffer: Option<usize>,
+            bytes_to_buffer: Option<usize>,
+        ) -> Self {
+            Self::from_unbuffered_reader_with_read_ahead(
+                reader,
+                lines_to_buffer,
+                bytes_to_buffer,
+                None,
+            )
+        }
+
+        /// Like `from_unbuffered_reader_with_byte_limit` but reads from `reader` in chunks of
+        /// `read_ahead` bytes (8 KiB if not set), also once past the buffered lines. Larger chunks
+        /// mean fewer reads from high-latency sources at the cost of keeping more bytes in memory.
+        pub fn from_unbuffered_reader_with_read_ahead(
+            reader: R,
+            lines_to_buffer: Option<usize>,
+            bytes_to_buffer: Option<usize>,
+            read_ahead: Option<usize>,
+        ) -> Self {
+            let read_ahead = read_ahead.unwrap_or(BUFFER_SIZE).max(1);
             let mut inner = reader;
-            let mut buffer = Vec::<u8>::with_capacity(BUFFER_SIZE);
+            let mut buffer = Vec::<u8>::with_capacity(read_ahead);
             let mut lines = 0;
             let mut bytes_read = 0;
             loop {
                 let bytes_before = bytes_read;
-                buffer.append(&mut vec![0; BUFFER_SIZE - (buffer.len() - bytes_read)]);
+                buffer.append(&mut vec![0; read_ahead - (buffer.len() - bytes_read)]);
                 bytes_read += inner
-                    .read(&mut buffer[bytes_read..bytes_read + BUFFER_SIZE])
+                    .read(&mut buffer[bytes_read..bytes_read + read_ahead])
                     .unwrap();
                 lines += buffer[bytes_before..bytes_read]
                     .iter()
@@ -43,6 +75,11 @@ pub mod seekable_reader {
                         break;
                     }
                 }
+                if let Some(bytes_to_buffer) = bytes_to_buffer {
+                    if bytes_read >= bytes_to_buffer {
+                        break;
+                    }
+                }
                 if bytes_read - bytes_before == 0 {
                     break;
                 }
@@ -53,6 +90,22 @@ pub mod seekable_reader {
                 buffered_bytes: bytes_read,
                 pos: 0,
                 seekable: true,
+                read_ahead,
+                ahead: Vec::new(),
+                ahead_len: 0,
+                ahead_pos: 0,
+            }
+        }
+    }
+
+    impl<R> SeekableReader<R> {
+        /// Length of the longest buffered prefix of at most `limit` bytes that ends with a
+        /// complete line, or `limit` if there is no line break within it.
+        pub fn buffered_lines_len(&self, limit: usize) -> usize {
+            let limit = limit.min(self.buffered_bytes);
+            match self.buffer[..limit].iter().rposition(|&x| x == 10) {
+                Some(pos) => pos + 1,
+                None => limit,
             }
         }
     }
@@ -72,9 +125,34 @@ pub mod seekable_reader {
                     self.pos += buf_len;
                     Ok(buf_len)
                 }
+            } else if self.ahead_pos < self.ahead_len {
+                let len_read = buf_len.min(self.ahead_len - self.ahead_pos);
+                buf[..len_read]
+                    .copy_from_slice(&self.ahead[self.ahead_pos..self.ahead_pos + len_read]);
+                self.ahead_pos += len_read;
+                Ok(len_read)
+            } else if buf_len >= self.read_ahead {
+                let len_read = self.inner.read(buf)?;
+                // Reaching the end of the input doesn't move us past the buffer.
+                if len_read > 0 {
+                    self.seekable = false;
+                }
+                Ok(len_read)
             } else {
+                if self.ahead.is_empty() {
+                    self.ahead = vec![0; self.read_ahead];
+                }
+                self.ahead_len = self.inner.read(&mut self.ahead)?;
+                self.ahead_pos = 0;
+                if self.ahead_len == 0 {
+                    return Ok(0);
+                }
                 self.seekable = false;
-                self.inner.read(buf)
+
+                let len_read = buf_len.min(self.ahead_len);
+                buf[..len_read].copy_from_slice(&self.ahead[..len_read]);
+                self.ahead_pos = len_read;
+                Ok(len_read)
             }
         }
     }
@@ -88,7 +166,7 @@ pub mod seekable_reader {
             if self.seekable {
                 match pos {
                     io::SeekFrom::Start(pos) => {
-                        if pos >= self.buffered_bytes as u64 {
+                        if pos > self.buffered_bytes as u64 {
                             error
                         } else {
                             self.pos = pos as usize;
@@ -97,7 +175,7 @@ pub mod seekable_reader {
                     }
                     io::SeekFrom::Current(pos) => {
                         let new_pos = self.pos as i64 + pos;
-                        if 0 <= new_pos && new_pos < self.buffered_bytes as i64 {
+                        if 0 <= new_pos && new_pos <= self.buffered_bytes as i64 {
                             self.pos = new_pos as usize;
                             Ok(new_pos as u64)
                         } else {
@@ -112,3 +190,340 @@ pub mod seekable_reader {
         }
     }
 }
+
+/// The parquet writer options the tools share, on the command line and in their libraries.
+pub mod parquet_writer {
+    use parquet::basic::{BrotliLevel, Compression, Encoding, GzipLevel, ZstdLevel};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder};
+    use std::str::FromStr;
+
+    #[derive(clap::ValueEnum, Clone)]
+    #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+    pub enum ParquetCompression {
+        UNCOMPRESSED,
+        SNAPPY,
+        GZIP,
+        LZO,
+        BROTLI,
+        LZ4,
+        ZSTD,
+        LZ4_RAW,
+    }
+
+    #[derive(clap::ValueEnum, Clone)]
+    #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+    pub enum ParquetEncoding {
+        PLAIN,
+        PLAIN_DICTIONARY,
+        RLE,
+        RLE_DICTIONARY,
+        DELTA_BINARY_PACKED,
+        DELTA_LENGTH_BYTE_ARRAY,
+        DELTA_BYTE_ARRAY,
+        BYTE_STREAM_SPLIT,
+    }
+
+    #[derive(clap::ValueEnum, Clone)]
+    #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+    pub enum ParquetEnabledStatistics {
+        None,
+        Chunk,
+        Page,
+    }
+
+    impl From<&ParquetCompression> for Compression {
+        fn from(compression: &ParquetCompression) -> Self {
+            match compression {
+                ParquetCompression::UNCOMPRESSED => Compression::UNCOMPRESSED,
+                ParquetCompression::SNAPPY => Compression::SNAPPY,
+                ParquetCompression::GZIP => Compression::GZIP(GzipLevel::default()),
+                ParquetCompression::LZO => Compression::LZO,
+                ParquetCompression::BROTLI => Compression::BROTLI(BrotliLevel::default()),
+                ParquetCompression::LZ4 => Compression::LZ4,
+                ParquetCompression::ZSTD => Compression::ZSTD(ZstdLevel::default()),
+                ParquetCompression::LZ4_RAW => Compression::LZ4_RAW,
+            }
+        }
+    }
+
+    impl From<&ParquetEncoding> for Encoding {
+        fn from(encoding: &ParquetEncoding) -> Self {
+            match encoding {
+                ParquetEncoding::PLAIN => Encoding::PLAIN,
+                ParquetEncoding::PLAIN_DICTIONARY => Encoding::PLAIN_DICTIONARY,
+                ParquetEncoding::RLE => Encoding::RLE,
+                ParquetEncoding::RLE_DICTIONARY => Encoding::RLE_DICTIONARY,
+                ParquetEncoding::DELTA_BINARY_PACKED => Encoding::DELTA_BINARY_PACKED,
+                ParquetEncoding::DELTA_LENGTH_BYTE_ARRAY => Encoding::DELTA_LENGTH_BYTE_ARRAY,
+                ParquetEncoding::DELTA_BYTE_ARRAY => Encoding::DELTA_BYTE_ARRAY,
+                ParquetEncoding::BYTE_STREAM_SPLIT => Encoding::BYTE_STREAM_SPLIT,
+            }
+        }
+    }
+
+    impl From<&ParquetEnabledStatistics> for EnabledStatistics {
+        fn from(statistics: &ParquetEnabledStatistics) -> Self {
+            match statistics {
+                ParquetEnabledStatistics::Chunk => EnabledStatistics::Chunk,
+                ParquetEnabledStatistics::Page => EnabledStatistics::Page,
+                ParquetEnabledStatistics::None => EnabledStatistics::None,
+            }
+        }
+    }
+
+    /// Parses the names used on the command line, e.g. `lz4-raw`, ignoring case and accepting `_`
+    /// in place of `-`.
+    impl FromStr for ParquetCompression {
+        type Err = ParquetError;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            match normalize_variant(value).as_str() {
+                "uncompressed" => Ok(Self::UNCOMPRESSED),
+                "snappy" => Ok(Self::SNAPPY),
+                "gzip" => Ok(Self::GZIP),
+                "lzo" => Ok(Self::LZO),
+                "brotli" => Ok(Self::BROTLI),
+                "lz4" => Ok(Self::LZ4),
+                "zstd" => Ok(Self::ZSTD),
+                "lz4-raw" => Ok(Self::LZ4_RAW),
+                _ => Err(invalid_variant(
+                    "compression",
+                    value,
+                    "uncompressed, snappy, gzip, lzo, brotli, lz4, zstd, lz4-raw",
+                )),
+            }
+        }
+    }
+
+    /// Parses the names used on the command line, e.g. `delta-binary-packed`, ignoring case and
+    /// accepting `_` in place of `-`.
+    impl FromStr for ParquetEncoding {
+        type Err = ParquetError;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            match normalize_variant(value).as_str() {
+                "plain" => Ok(Self::PLAIN),
+                "plain-dictionary" => Ok(Self::PLAIN_DICTIONARY),
+                "rle" => Ok(Self::RLE),
+                "rle-dictionary" => Ok(Self::RLE_DICTIONARY),
+                "delta-binary-packed" => Ok(Self::DELTA_BINARY_PACKED),
+                "delta-length-byte-array" => Ok(Self::DELTA_LENGTH_BYTE_ARRAY),
+                "delta-byte-array" => Ok(Self::DELTA_BYTE_ARRAY),
+                "byte-stream-split" => Ok(Self::BYTE_STREAM_SPLIT),
+                _ => Err(invalid_variant(
+                    "encoding",
+                    value,
+                    "plain, plain-dictionary, rle, rle-dictionary, delta-binary-packed, \
+                     delta-length-byte-array, delta-byte-array, byte-stream-split",
+                )),
+            }
+        }
+    }
+
+    /// Parses the names used on the command line, ignoring case.
+    impl FromStr for ParquetEnabledStatistics {
+        type Err = ParquetError;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            match normalize_variant(value).as_str() {
+                "none" => Ok(Self::None),
+                "chunk" => Ok(Self::Chunk),
+                "page" => Ok(Self::Page),
+                _ => Err(invalid_variant("statistics", value, "none, chunk, page")),
+            }
+        }
+    }
+
+    /// Normalizes an option value for matching against the names of its variants, ignoring case
+    /// and accepting `_` in place of `-`.
+    pub fn normalize_variant(value: &str) -> String {
+        value.trim().to_ascii_lowercase().replace('_', "-")
+    }
+
+    /// The error for an option value that is none of the `valid` names.
+    pub fn invalid_variant(kind: &str, value: &str, valid: &str) -> ParquetError {
+        ParquetError::General(format!(
+            "Invalid {kind} \"{value}\", expected one of: {valid}"
+        ))
+    }
+
+    /// The writer options of the command line tools that write parquet files.
+    #[derive(clap::Args, Clone, Default)]
+    // Otherwise the doc comment above would replace the description of the tools.
+    #[clap(about = None, long_about = None)]
+    pub struct WriterArgs {
+        /// Set the compression.
+        #[clap(short, long, value_parser)]
+        pub compression: Option<ParquetCompression>,
+
+        /// Sets encoding for any column.
+        #[clap(short, long, value_parser)]
+        pub encoding: Option<ParquetEncoding>,
+
+        /// Sets data page size limit.
+        #[clap(long)]
+        pub data_page_size_limit: Option<usize>,
+
+        /// Sets dictionary page size limit.
+        #[clap(long)]
+        pub dictionary_page_size_limit: Option<usize>,
+
+        /// Sets write batch size.
+        #[clap(long)]
+        pub write_batch_size: Option<usize>,
+
+        /// Sets max size for a row group.
+        #[clap(long)]
+        pub max_row_group_size: Option<usize>,
+
+        /// Sets "created by" property.
+        #[clap(long)]
+        pub created_by: Option<String>,
+
+        /// Sets flag to enable/disable dictionary encoding for any column.
+        #[clap(long)]
+        pub dictionary: bool,
+
+        /// Sets flag to enable/disable statistics for any column.
+        #[clap(long, value_parser)]
+        pub statistics: Option<ParquetEnabledStatistics>,
+
+        /// Sets max statistics size for any column. Applicable only if statistics are enabled.
+        #[clap(long)]
+        pub max_statistics_size: Option<usize>,
+    }
+
+    impl WriterArgs {
+        /// Builds the parquet writer properties from these options.
+        pub fn writer_properties(&self) -> WriterProperties {
+            self.writer_properties_builder().build()
+        }
+
+        /// Starts the parquet writer properties from these options, for callers to add their own.
+        pub fn writer_properties_builder(&self) -> WriterPropertiesBuilder {
+            let mut props = WriterProperties::builder().set_dictionary_enabled(self.dictionary);
+
+            if let Some(ref statistics) = self.statistics {
+                props = props.set_statistics_enabled(statistics.into());
+            }
+
+            if let Some(ref compression) = self.compression {
+                props = props.set_compression(compression.into());
+            }
+
+            if let Some(ref encoding) = self.encoding {
+                props = props.set_encoding(encoding.into());
+            }
+
+            if let Some(size) = self.write_batch_size {
+                props = props.set_write_batch_size(size);
+            }
+
+            if let Some(size) = self.data_page_size_limit {
+                props = props.set_data_page_size_limit(size);
+            }
+
+            if let Some(size) = self.dictionary_page_size_limit {
+                props = props.set_dictionary_page_size_limit(size);
+            }
+
+            if let Some(size) = self.max_row_group_size {
+                props = props.set_max_row_group_size(size);
+            }
+
+            if let Some(ref created_by) = self.created_by {
+                props = props.set_created_by(created_by.clone());
+            }
+
+            if let Some(size) = self.max_statistics_size {
+                props = props.set_max_statistics_size(size);
+            }
+
+            props
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parquet_writer::{ParquetCompression, ParquetEnabledStatistics, ParquetEncoding};
+    use super::seekable_reader::SeekableReader;
+    use std::io::{Read, Seek};
+
+    #[test]
+    fn rewind_within_buffered_lines() {
+        let input = "a\n1\n2\n3\n4\n".as_bytes();
+        for read_ahead in [1, 3, 8192] {
+            let mut reader = SeekableReader::from_unbuffered_reader_with_read_ahead(
+                input,
+                Some(1),
+                None,
+                Some(read_ahead),
+            );
+            let mut start = [0; 4];
+            reader.read_exact(&mut start).unwrap();
+            assert_eq!(&start, b"a\n1\n");
+            reader.rewind().unwrap();
+
+            let mut all = String::new();
+            reader.read_to_string(&mut all).unwrap();
+            assert_eq!(all, "a\n1\n2\n3\n4\n");
+        }
+    }
+
+    #[test]
+    fn seek_fails_past_the_buffer() {
+        let input = "a\n1\n2\n3\n4\n".as_bytes();
+        let mut reader =
+            SeekableReader::from_unbuffered_reader_with_read_ahead(input, Some(0), None, Some(2));
+        let mut all = String::new();
+        reader.read_to_string(&mut all).unwrap();
+        assert!(reader.rewind().is_err());
+    }
+
+    #[test]
+    fn buffered_lines_len() {
+        let reader = SeekableReader::from_unbuffered_reader_with_byte_limit(
+            "ab\ncd\nef".as_bytes(),
+            None,
+            Some(5),
+        );
+        assert_eq!(reader.buffered_lines_len(5), 3);
+        assert_eq!(reader.buffered_lines_len(2), 2);
+    }
+
+    #[test]
+    fn parse_enum_names() {
+        assert!(matches!(
+            "lz4-raw".parse::<ParquetCompression>(),
+            Ok(ParquetCompression::LZ4_RAW)
+        ));
+        assert!(matches!(
+            " LZ4_Raw ".parse::<ParquetCompression>(),
+            Ok(ParquetCompression::LZ4_RAW)
+        ));
+        assert!(matches!(
+            "Byte_Stream_Split".parse::<ParquetEncoding>(),
+            Ok(ParquetEncoding::BYTE_STREAM_SPLIT)
+        ));
+        assert!(matches!(
+            "PAGE".parse::<ParquetEnabledStatistics>(),
+            Ok(ParquetEnabledStatistics::Page)
+        ));
+
+        let error = "lz5"
+            .parse::<ParquetCompression>()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("Invalid compression \"lz5\""));
+        assert!(error.contains("snappy, gzip"));
+        let error = "dict".parse::<ParquetEncoding>().err().unwrap().to_string();
+        assert!(error.contains("Invalid encoding \"dict\""));
+        let error = "all".parse::<ParquetEnabledStatistics>().err().unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("expected one of: none, chunk, page"));
+    }
+}
@@ -1,67 +1,361 @@
-use arrow::{array::{Array, ArrayRef, GenericByteBuilder, LargeStringArray, RecordBatch, StringArray}, csv::{reader::Format, ReaderBuilder}, datatypes::GenericStringType};
-use arrow_schema::{DataType, Schema};
+use arrow::{
+    array::{
+        make_comparator, Array, ArrayRef, ArrowPrimitiveType, AsArray, BinaryArray, BooleanArray,
+        GenericByteBuilder, RecordBatch, Scalar, StringArray, UInt64Array,
+    },
+    compute::{
+        cast, cast_with_options, is_null,
+        kernels::{cmp::eq, zip::zip},
+        nullif, CastOptions,
+    },
+    csv::{reader::Format, ReaderBuilder},
+    datatypes::{Float16Type, Float32Type, Float64Type, GenericStringType},
+    error::ArrowError,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, SortOptions, TimeUnit};
 use arrow_tools::seekable_reader::*;
+use log::{debug, info, warn, LevelFilter};
 use parquet::{
-    arrow::ArrowWriter,
-    basic::{BrotliLevel, Compression, Encoding, GzipLevel, ZstdLevel},
+    arrow::{
+        arrow_reader::ParquetRecordBatchReaderBuilder, arrow_to_parquet_schema,
+        arrow_writer::ArrowWriterOptions, ArrowWriter,
+    },
+    basic::{Encoding, Type as PhysicalType},
     errors::ParquetError,
-    file::properties::{EnabledStatistics, WriterProperties},
+    file::{
+        metadata::ParquetMetaDataReader,
+        properties::{
+            EnabledStatistics, WriterProperties, WriterPropertiesBuilder,
+            DEFAULT_MAX_ROW_GROUP_SIZE,
+        },
+        FOOTER_SIZE,
+    },
+    format::{FileMetaData, KeyValue, SortingColumn},
+    schema::types::ColumnPath,
+    thrift::{TCompactOutputProtocol, TSerializable},
+};
+use regex::RegexSet;
+use serde_json::error::Category;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+use tempfile::NamedTempFile;
+use twox_hash::XxHash64;
+use zip::ZipArchive;
+
+use arrow_tools::parquet_writer::{invalid_variant, normalize_variant, WriterArgs};
+pub use arrow_tools::parquet_writer::{
+    ParquetCompression, ParquetEnabledStatistics, ParquetEncoding,
 };
-use regex::Regex;
-use std::{path::PathBuf, str::FromStr};
-use std::sync::Arc;
-use std::{fs::File, io::Seek};
-
-#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-pub enum ParquetCompression {
-    UNCOMPRESSED,
-    SNAPPY,
-    GZIP,
-    LZO,
-    BROTLI,
-    LZ4,
-    ZSTD,
-    LZ4_RAW,
-}
-
-#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-pub enum ParquetEncoding {
-    PLAIN,
-    PLAIN_DICTIONARY,
-    RLE,
-    RLE_DICTIONARY,
-    DELTA_BINARY_PACKED,
-    DELTA_LENGTH_BYTE_ARRAY,
-    DELTA_BYTE_ARRAY,
-    BYTE_STREAM_SPLIT,
-}
-
-#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-pub enum ParquetEnabledStatistics {
-    None,
-    Chunk,
-    Page,
+
+/// How to handle data rows with more or fewer fields than the header.
+#[derive(Clone)]
+pub enum RaggedMode {
+    /// Error on the first row with the wrong number of fields.
+    Fail,
+    /// Fill missing trailing fields with nulls.
+    Pad,
+    /// Fill missing trailing fields with nulls and drop extra fields.
+    Truncate,
+}
+
+/// How quotes inside quoted fields are written.
+#[derive(Clone, Copy)]
+pub enum QuoteStyle {
+    /// A quote is written twice, e.g. `"say ""hi"""`. `escape` is a plain character.
+    Doubling,
+    /// A quote is preceded by `escape`, e.g. `"say \"hi\""`. A quote always ends the quoted part
+    /// of a field, so `""` is an empty field rather than an escaped quote.
+    Escape,
+    /// Either of the above, in the same file.
+    Both,
+}
+
+/// Width of the integer type that inferred integer columns are read as.
+#[derive(Clone, Copy)]
+pub enum IntWidth {
+    I8,
+    I16,
+    I32,
+    I64,
+}
+
+/// The separators of numbers written in a locale's format, e.g. `1.234,5` in much of Europe.
+#[derive(Clone, Copy)]
+pub struct NumberFormat {
+    pub decimal_separator: char,
+    /// Separator between groups of digits, removed before parsing.
+    pub thousands_separator: Option<char>,
+}
+
+impl NumberFormat {
+    /// `1,234.5`
+    pub const US: Self = Self {
+        decimal_separator: '.',
+        thousands_separator: Some(','),
+    };
+    /// `1.234,5`
+    pub const EU: Self = Self {
+        decimal_separator: ',',
+        thousands_separator: Some('.'),
+    };
+
+    /// Rewrites a number in this format to the plain format arrow parses.
+    fn normalize(&self, value: &str) -> String {
+        value
+            .trim()
+            .chars()
+            .filter(|c| Some(*c) != self.thousands_separator)
+            .map(|c| if c == self.decimal_separator { '.' } else { c })
+            .collect()
+    }
+}
+
+/// How decimal values with more fractional digits than the column's scale are rounded.
+#[derive(Clone, Copy)]
+pub enum RoundingMode {
+    /// Round to the nearest value, ties away from zero.
+    HalfUp,
+    /// Round to the nearest value, ties to the even neighbour.
+    HalfEven,
+    /// Drop the extra digits.
+    Truncate,
 }
 
+#[derive(Clone)]
 pub struct Opts {
-    /// Input CSV fil, stdin if not present.
+    /// Input CSV fil, stdin if not present. A `.zip` archive is read if it contains a single CSV file.
+    /// `.bz2` and `.xz` files are decompressed with the `bzip2` and `xz` features, and UTF-16
+    /// input starting with a byte order mark is transcoded to UTF-8 with the `encoding` feature.
     pub input: PathBuf,
 
-    /// Output file.
+    /// Convert every `.csv` file in this directory to a `.parquet` file with the same base name in
+    /// the `output` directory instead of converting `input`. The directory must contain at least
+    /// one. Only supported by [`convert`].
+    pub input_dir: Option<PathBuf>,
+
+    /// Skip empty files in `input_dir` instead of failing on them. At least one file must not be
+    /// empty.
+    pub skip_empty_files: bool,
+
+    /// Name of the CSV file to read when the input is a `.zip` archive with several entries.
+    pub zip_entry: Option<String>,
+
+    /// Output file. With the `object_store` feature this can be a URL like
+    /// `s3://bucket/key.parquet`, uploaded while writing with credentials from the `AWS_`,
+    /// `GOOGLE_`, or `AZURE_` environment variables.
     pub output: PathBuf,
 
     /// File with Arrow schema in JSON format.
     pub schema: Option<Schema>,
 
+    /// Arrow schema as a compact column spec, e.g. `id:int64!,name:string,price:decimal(10,2)`. A trailing `!` marks a column as non-null.
+    pub schema_spec: Option<String>,
+
     /// The number of records to infer the schema from. All rows if not present. Setting max-read-records to zero will stop schema inference and all columns will be string typed.
     pub max_read_records: Option<usize>,
 
+    /// The number of bytes to buffer for schema inference when the input is not seekable (e.g. a pipe). Inference stops at whichever of this and max-read-records is reached first.
+    pub max_infer_bytes: Option<usize>,
+
+    /// Bytes read at once from input that is not a regular file, 8 KiB if not set. Larger values
+    /// help with high-latency sources but keep more of the input in memory.
+    pub read_ahead: Option<usize>,
+
+    /// Buffer the input for schema inference like piped input even if it is a regular file,
+    /// instead of rewinding it. For files that report being seekable but can't be rewound reliably.
+    pub force_streaming: bool,
+
+    /// Set whether the CSV file has headers
+    pub header: Option<bool>,
+
+    /// Set the number of header rows. With more than one, the rows are combined into one column name per column.
+    pub header_rows: Option<usize>,
+
+    /// Separator placed between the parts of a column name combined from several header rows.
+    pub header_separator: String,
+
     /// Set the CSV file's column delimiter as a byte character.
     pub delimiter: char,
 
+    /// Detect the delimiter from the first lines, choosing among `,`, `\t`, `;`, and `|`. Falls back
+    /// to `delimiter` if no candidate splits the lines consistently.
+    pub detect_delimiter: bool,
+
+    /// Detect the delimiter like `detect_delimiter` and, if `header` is not set, whether the first
+    /// line is a header, e.g. because it has text in a numeric column. The quote is always `"`.
+    pub sniff_dialect: bool,
+
     /// Set the CSV file's column escape as a byte character.
     pub escape: char,
 
+    /// How quotes are escaped in quoted fields. With `Doubling`, `escape` is ignored so that a
+    /// quoted value may end with it, e.g. `"C:\dir\"`.
+    pub quote_style: QuoteStyle,
+
+    /// Fail on input that mixes `\n`, `\r\n`, and `\r` line endings instead of accepting all of
+    /// them. Line breaks in quoted fields are not checked.
+    pub strict_line_endings: bool,
+
+    /// Rewrite `\r\n` and `\r` line endings to `\n` before parsing, for files from old Macs or with
+    /// mixed endings. Line breaks in quoted fields are kept as they are.
+    pub normalize_newlines: bool,
+
+    /// Reorder the CSV columns, matched by header name, to the field order of the provided schema.
+    pub reorder_to_schema: bool,
+
+    /// Reorder the fields of the provided schema, matched by name, to the order of the CSV header,
+    /// so the output has the columns in CSV order.
+    pub match_schema_to_header: bool,
+
+    /// Write the columns in this order, e.g. to place related columns together. Must list every
+    /// column once.
+    pub output_column_order: Vec<String>,
+
+    /// Types that inference may pick, in order of preference, out of `boolean`, `integer`,
+    /// `float`, `date`, `timestamp`, and `string`. A column gets the first type that all its
+    /// sampled values fit, integers also fitting floats and dates timestamps, and is a string
+    /// otherwise. Types after `string` are never picked. Defaults to the arrow inference.
+    pub type_priority: Option<Vec<String>>,
+
+    /// Fail if inference reads every one of several columns as a string, which often means the
+    /// delimiter or another setting is wrong.
+    pub fail_on_all_string: bool,
+
+    /// Accept integers with a leading `+`, leading zeros, or surrounding spaces. Without it the
+    /// strict arrow parsing and inference applies and such values are read as strings.
+    pub lenient_integers: bool,
+
+    /// Read integer columns with values that don't fit in 64 bits as `Decimal128(38, 0)` instead of
+    /// strings.
+    pub large_int_as_decimal: bool,
+
+    /// Round decimal values with more fractional digits than the column's scale. The digits are
+    /// rounded as written, so there is no float error. Extra digits are dropped if not set.
+    pub decimal_rounding: Option<RoundingMode>,
+
+    /// Number formats for specific columns as `(column, format)`, so columns in the same file can
+    /// use different decimal and thousands separators. Inferred columns are read as `Float64`,
+    /// columns of a provided schema must be numeric.
+    pub column_number_format: Vec<(String, NumberFormat)>,
+
+    /// Read inferred timestamp columns with this time unit. Values with a finer precision are
+    /// truncated.
+    pub timestamp_unit: Option<TimeUnit>,
+
+    /// Always read these columns as strings, regardless of the inferred type.
+    pub string_columns: Vec<String>,
+
+    /// Only infer the types of these columns and read all others as strings, skipping the
+    /// inference work for them. All columns are inferred if empty.
+    pub infer_only_columns: Vec<String>,
+
+    /// Read these columns as `Float32` instead of the inferred type to save space. Values are
+    /// rounded to about 7 significant digits.
+    pub float32_columns: Vec<String>,
+
+    /// Write NaN values of float columns as nulls, so aggregations downstream skip them. NaN in
+    /// non-null columns fails the conversion.
+    pub nan_as_null: bool,
+
+    /// Read inferred integer columns with this width instead of 64 bits. Values out of range for
+    /// the width fail the conversion.
+    pub integer_width: Option<IntWidth>,
+
+    /// Widths for specific inferred integer columns as `(column, width)`, overriding
+    /// `integer_width`.
+    pub column_integer_width: Vec<(String, IntWidth)>,
+
+    /// Read these inferred integer columns as unsigned integers of the same width. Negative
+    /// values fail the conversion.
+    pub unsigned_columns: Vec<String>,
+
+    /// Rename duplicate column names in the header by appending a suffix (`id`, `id_1`) instead of
+    /// failing.
+    pub dedup_header_names: bool,
+
+    /// Values that are read as null in a specific column, as (column, value) pairs. A column can have several values.
+    pub column_null_values: Vec<(String, String)>,
+
+    /// Write this value instead of null and mark the columns non-null, for readers that don't
+    /// support nulls. Numeric and other columns get the value parsed to their type and keep their
+    /// nulls if it doesn't parse. Nulls can't be told apart from the value in the output.
+    pub null_sentinel: Option<String>,
+
+    /// Values written instead of null in a specific column as `(column, value)`, parsed to the
+    /// type of the column. The columns are marked non-null and take precedence over
+    /// `null_sentinel`.
+    pub column_defaults: Vec<(String, String)>,
+
+    /// Stop converting before the output would grow past this many bytes, closing a valid file
+    /// with the row groups written so far. It stops between row groups of `max_row_group_size`
+    /// rows, guessing the size of the next one and its footer metadata from the previous one, so
+    /// the output can still end up slightly larger.
+    pub max_output_bytes: Option<usize>,
+
+    /// Read quoted empty fields (`""`) in string columns as null. If false, they are read as empty strings.
+    pub quoted_empty_as_null: bool,
+
+    /// Turn the escape sequences `\n` and `\t` in string values into a newline and a tab, for
+    /// exports that escape them in unquoted fields. An escaped backslash `\\` becomes a single
+    /// one.
+    pub unescape_newlines: bool,
+
+    /// Read unquoted empty fields in string columns as null. If false, they are read as empty
+    /// strings. Defaults to true.
+    pub empty_as_null: Option<bool>,
+
+    /// Columns that are written as non-nullable. The conversion fails if one of them contains a null.
+    pub non_null_columns: Vec<String>,
+
+    /// Key-value metadata attached to columns as `(column, key, value)`, e.g. units or descriptions.
+    pub field_metadata: Vec<(String, String, String)>,
+
+    /// Descriptions of columns as `(column, description)`, stored as the `description` field
+    /// metadata key read by several catalogs.
+    pub column_descriptions: Vec<(String, String)>,
+
+    /// Key-value metadata written to the parquet footer as `(key, value)`.
+    pub metadata: Vec<(String, String)>,
+
+    /// File with a JSON object of string keys and values to add to the footer metadata, read
+    /// when converting. Entries in `metadata` override entries of the file with the same key.
+    pub metadata_file: Option<PathBuf>,
+
+    /// Columns holding hex-encoded WKB geometries, written as binary with GeoParquet `geo`
+    /// metadata. The first one is the primary geometry column. The `geo` key cannot also be set in
+    /// `metadata`.
+    pub geometry_columns: Vec<String>,
+
+    /// Parquet physical types for specific columns as `(column, type)`, e.g. `INT32` or
+    /// `BYTE_ARRAY`. The column is read as an arrow type written with that physical type: integers
+    /// can be written as `INT32` or `INT64`, numbers as `FLOAT` or `DOUBLE`, and any column as
+    /// `BYTE_ARRAY` text.
+    pub physical_types: Vec<(String, String)>,
+
+    /// Set how rows with the wrong number of fields are handled.
+    pub on_ragged: RaggedMode,
+
+    /// Drop the empty last field of records that end with a delimiter, e.g. `a,b,`, before
+    /// inferring the schema and reading.
+    pub ignore_trailing_delimiter: bool,
+
+    /// Only convert the data rows from `start` up to but not including `end`, counting from zero.
+    pub row_range: Option<(usize, usize)>,
+
+    /// Add a last column `__line__` with the line of the input each row starts on, counting from
+    /// one, to trace rows back to the input after rows were dropped, e.g. with `row_range`.
+    pub preserve_line_numbers: bool,
+
     /// Set the compression.
     pub compression: Option<ParquetCompression>,
 
@@ -80,219 +374,5108 @@ pub struct Opts {
     /// Sets max size for a row group.
     pub max_row_group_size: Option<usize>,
 
+    /// Ends row groups before their data would take more than about this many bytes of memory.
+    /// The bytes per row are estimated from the first batch, so row groups of later rows with
+    /// larger values can still take more.
+    pub row_group_memory_budget: Option<usize>,
+
+    /// Write all rows as a single row group, for readers that work best with one. The whole output
+    /// is buffered in memory until the file is closed.
+    pub single_row_group: bool,
+
+    /// Sets the max size for a row group so the output has about this many row groups. The rows
+    /// of input files are counted before writing, other input is written first and the file then
+    /// rewritten into row groups of that size, so it needs an output file.
+    pub target_row_groups: Option<usize>,
+
     /// Sets "created by" property.
     pub created_by: Option<String>,
 
+    /// Sets "created by" property to `<name> version <version> (csv2parquet version <version>)`
+    /// from `(name, version)` of the tool running the conversion, unless `created_by` is set.
+    pub created_by_tool: Option<(String, String)>,
+
+    /// Don't embed the arrow schema as `ARROW:schema` metadata, for readers that don't accept it.
+    /// Arrow readers then derive the types from the parquet schema.
+    pub skip_arrow_schema_metadata: bool,
+
     /// Sets flag to enable/disable dictionary encoding for any column.
     pub dictionary: bool,
 
+    /// Columns that are never dictionary encoded, regardless of `dictionary`.
+    pub no_dictionary_columns: Vec<String>,
+
+    /// Also never dictionary encode columns whose values are longer than this many bytes on
+    /// average, measured on the records read for schema inference.
+    pub no_dictionary_for_large_columns: Option<usize>,
+
     /// Sets flag to enable/disable statistics for any column.
     pub statistics: Option<ParquetEnabledStatistics>,
 
+    /// Disable statistics for columns that turn out to contain only nulls, for readers that
+    /// mishandle statistics without min and max values. Statistics are chosen before writing, so
+    /// if there are such columns the output is read back and rewritten.
+    pub omit_stats_for_null_columns: bool,
+
     /// Sets max statistics size for any column. Applicable only if statistics are enabled.
     pub max_statistics_size: Option<usize>,
 
+    /// Sets max statistics size for specific columns as `(column, size)`, overriding `max_statistics_size`.
+    pub column_max_statistics_size: Vec<(String, usize)>,
+
+    /// Separator between the parts of a nested column path in per-column writer options. Defaults
+    /// to `.`, so `addr.city` refers to `city` in the struct column `addr`. Set it to another
+    /// character, or to `None` to use the names as they are, if column names contain dots.
+    pub column_path_separator: Option<char>,
+
+    /// Record the first column as the sort key of the file and write a bloom filter for it. The
+    /// column is assumed to be sorted ascending with nulls last, which is only checked together
+    /// with `verify_after_write`.
+    pub first_column_is_key: bool,
+
+    /// Check while writing that the key column declared with `first_column_is_key` is sorted, and
+    /// fail with the first out of order row if not.
+    pub verify_sorted: bool,
+
     /// Print the schema to stderr.
     pub print_schema: bool,
 
     /// Only print the schema
     pub dry: bool,
+
+    /// Print the schema as compact single-line JSON instead of pretty-printing it.
+    pub schema_json_compact: bool,
+
+    /// Write the schema as JSON to this file, usable as a schema file later, and stop without
+    /// converting.
+    pub extract_schema: Option<PathBuf>,
+
+    /// Arrow schema in JSON format to compare the output schema against, logging a warning for
+    /// every column that was added, removed, or changed type.
+    pub baseline_schema: Option<PathBuf>,
+
+    /// Fail instead of warning when the output schema differs from `baseline_schema`.
+    pub fail_on_schema_drift: bool,
+
+    /// Sets the verbosity, see [`Opts::log_level`].
+    pub verbose: u8,
+
+    /// Write a JSON manifest describing the output to this file. When the schema was inferred,
+    /// `inferred_records` holds the number of records the inference actually read.
+    pub manifest: Option<PathBuf>,
+
+    /// Write the mapping of CSV header names to output column names, in CSV column order, to this
+    /// file. It is a CSV file with `source` and `column` columns if the path ends in `.csv`, a JSON
+    /// array of objects otherwise. Several header rows appear as their combined name, and the
+    /// source is empty without a header. With `input_dir`, one map covers all the files, with the
+    /// input file name of every column in a `file` column or key.
+    pub write_header_map: Option<PathBuf>,
+
+    /// Write progress events as JSON lines to this file, e.g. a named pipe read by a progress UI:
+    /// `{"event":"batch","rows":N}` after each batch, `{"event":"row_group_flush"}` after each row
+    /// group, and `{"event":"done","rows":N}` once the file is complete. With `input_dir`, the
+    /// events of all the files follow each other in order, each file ending with its `done`.
+    pub events: Option<PathBuf>,
+
+    /// Print an estimate of the output size from encoding a sample of the input, without writing the output.
+    pub estimate: bool,
+
+    /// Only print the number of data rows, within `row_range` if set, without inferring a schema
+    /// or writing the output.
+    pub count_only: bool,
+
+    /// Log the time spent reading and writing with the throughput at the info level.
+    pub benchmark: bool,
+
+    /// Read the output back after writing and check that it contains every written row.
+    pub verify_after_write: bool,
+
+    /// Write to a temporary file in this directory first and move it to the output once complete.
+    pub temp_dir: Option<PathBuf>,
+
+    /// Capacity in bytes of the buffer used when writing the output. Larger buffers help on slow storage.
+    pub write_buffer_size: Option<usize>,
+
+    /// Build the whole parquet file in memory and write it out once complete. See also
+    /// [`convert_to_vec`], which returns the bytes instead of writing them.
+    pub buffer_in_memory: bool,
+
+    /// Write an empty `_SUCCESS` file next to the output once the conversion has completed.
+    pub success_marker: bool,
+
+    /// Store an xxHash64 of every byte of the output before the footer metadata, i.e. the leading
+    /// magic number and all row groups, as `content_hash` key-value metadata. The footer is
+    /// written before the hash is known, so the output is read back and only its footer is
+    /// replaced, leaving the hashed bytes as they are.
+    pub content_hash: bool,
+
+    /// On SIGINT, stop reading and finish the current row group and footer so the output is a
+    /// valid parquet file with the rows read so far, then fail with an error. The output only
+    /// contains part of the input in this case.
+    #[cfg(feature = "interrupt")]
+    pub finalize_on_interrupt: bool,
 }
 
 impl Opts {
     pub fn new(input: PathBuf, output: PathBuf) -> Self {
         Self {
             input,
+            input_dir: None,
+            skip_empty_files: true,
             output,
+            zip_entry: None,
             schema: None,
+            schema_spec: None,
             max_read_records: None,
+            max_infer_bytes: None,
+            read_ahead: None,
+            force_streaming: false,
+            header: None,
+            header_rows: None,
+            header_separator: "_".to_string(),
             delimiter: ',',
+            detect_delimiter: false,
+            sniff_dialect: false,
             escape: '\\',
+            quote_style: QuoteStyle::Both,
+            strict_line_endings: false,
+            normalize_newlines: false,
+            reorder_to_schema: false,
+            match_schema_to_header: false,
+            output_column_order: Vec::new(),
+            type_priority: None,
+            fail_on_all_string: false,
+            lenient_integers: false,
+            large_int_as_decimal: false,
+            decimal_rounding: None,
+            column_number_format: Vec::new(),
+            timestamp_unit: None,
+            string_columns: Vec::new(),
+            infer_only_columns: Vec::new(),
+            float32_columns: Vec::new(),
+            integer_width: None,
+            column_integer_width: Vec::new(),
+            unsigned_columns: Vec::new(),
+            dedup_header_names: false,
+            column_null_values: Vec::new(),
+            null_sentinel: None,
+            column_defaults: Vec::new(),
+            max_output_bytes: None,
+            quoted_empty_as_null: true,
+            unescape_newlines: false,
+            nan_as_null: false,
+            empty_as_null: None,
+            non_null_columns: Vec::new(),
+            field_metadata: Vec::new(),
+            column_descriptions: Vec::new(),
+            metadata: Vec::new(),
+            metadata_file: None,
+            geometry_columns: Vec::new(),
+            physical_types: Vec::new(),
+            on_ragged: RaggedMode::Fail,
+            ignore_trailing_delimiter: false,
+            row_range: None,
+            preserve_line_numbers: false,
             compression: None,
             encoding: None,
             data_page_size_limit: None,
             dictionary_page_size_limit: None,
             write_batch_size: None,
             max_row_group_size: None,
+            row_group_memory_budget: None,
+            single_row_group: false,
+            target_row_groups: None,
             created_by: None,
+            created_by_tool: None,
+            skip_arrow_schema_metadata: false,
             dictionary: false,
+            no_dictionary_columns: Vec::new(),
+            no_dictionary_for_large_columns: None,
             statistics: None,
+            omit_stats_for_null_columns: false,
             max_statistics_size: None,
+            column_max_statistics_size: Vec::new(),
+            column_path_separator: Some('.'),
+            first_column_is_key: false,
+            verify_sorted: false,
             print_schema: false,
             dry: false,
+            schema_json_compact: false,
+            extract_schema: None,
+            baseline_schema: None,
+            fail_on_schema_drift: false,
+            verbose: 0,
+            manifest: None,
+            write_header_map: None,
+            events: None,
+            estimate: false,
+            count_only: false,
+            benchmark: false,
+            verify_after_write: false,
+            temp_dir: None,
+            write_buffer_size: None,
+            buffer_in_memory: false,
+            success_marker: false,
+            content_hash: false,
+            #[cfg(feature = "interrupt")]
+            finalize_on_interrupt: false,
         }
     }
-}
 
-pub fn convert(opts: Opts) -> Result<(), ParquetError> {
-    let mut file = File::open(&opts.input)?;
+    /// Builds the parquet writer properties from these options.
+    pub fn writer_properties(&self) -> WriterProperties {
+        self.writer_properties_builder().build()
+    }
 
-    let mut input: Box<dyn SeekRead> = if file.rewind().is_ok() {
-        Box::new(file)
-    } else {
-        Box::new(SeekableReader::from_unbuffered_reader(
-            file,
-            opts.max_read_records,
-        ))
-    };
+    /// Builds the arrow writer options from these options for the columns of the output.
+    pub fn writer_options_for_schema(&self, schema: &Schema) -> ArrowWriterOptions {
+        self.writer_options(self.writer_properties_for_schema(schema))
+    }
 
-    let schema = match opts.schema {
-        Some(schema) => Ok(schema),
-        _ => {
-            let format = Format::default()
-                .with_delimiter(opts.delimiter as u8)
-                .with_escape(opts.escape as u8)
-                .with_quote(b'"');
+    fn writer_options(&self, props: WriterProperties) -> ArrowWriterOptions {
+        ArrowWriterOptions::new()
+            .with_properties(props)
+            .with_skip_arrow_metadata(self.skip_arrow_schema_metadata)
+    }
 
-            match format.infer_schema(&mut input, opts.max_read_records) {
-                Ok((schema, _size)) => Ok(schema),
-                Err(error) => Err(ParquetError::General(format!(
-                    "Error inferring schema: {error}"
-                ))),
+    /// Builds the parquet writer properties from these options, including the ones that depend
+    /// on the columns of the output.
+    pub fn writer_properties_for_schema(&self, schema: &Schema) -> WriterProperties {
+        self.writer_properties_builder_for_schema(schema).build()
+    }
+
+    fn writer_properties_builder_for_schema(&self, schema: &Schema) -> WriterPropertiesBuilder {
+        let mut props = self.writer_properties_builder();
+
+        if self.first_column_is_key {
+            if let Some(field) = schema.fields().first() {
+                props = props
+                    .set_sorting_columns(Some(vec![SortingColumn::new(0, false, false)]))
+                    .set_column_bloom_filter_enabled(ColumnPath::from(field.name().as_str()), true);
             }
         }
-    }?;
 
-    if opts.print_schema || opts.dry {
-        let json = serde_json::to_string_pretty(&schema).unwrap();
-        eprintln!("Schema:");
-        println!("{json}");
-        if opts.dry {
-            return Ok(());
+        let geometry_columns: Vec<&String> = self
+            .geometry_columns
+            .iter()
+            .filter(|column| schema.field_with_name(column).is_ok())
+            .collect();
+        if let Some(primary) = geometry_columns.first() {
+            let columns: serde_json::Map<_, _> = geometry_columns
+                .iter()
+                .map(|column| {
+                    (
+                        column.to_string(),
+                        serde_json::json!({ "encoding": "WKB", "geometry_types": [] }),
+                    )
+                })
+                .collect();
+            let geo = serde_json::json!({
+                "version": "1.1.0",
+                "primary_column": primary,
+                "columns": columns,
+            });
+            let mut metadata = self.key_value_metadata();
+            metadata.push(KeyValue::new("geo".to_string(), geo.to_string()));
+            props = props.set_key_value_metadata(Some(metadata));
         }
+
+        props
     }
 
-    let schema_ref = Arc::new(schema);
-    let builder = ReaderBuilder::new(schema_ref)
-        .with_delimiter(opts.delimiter as u8)
-        .with_escape(opts.escape as u8)
-        .with_quote(b'"');
+    fn key_value_metadata(&self) -> Vec<KeyValue> {
+        self.metadata
+            .iter()
+            .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+            .collect()
+    }
 
-    let reader = builder.build(input)?;
+    /// The options shared with the other tools that write parquet files.
+    fn writer_args(&self) -> WriterArgs {
+        let created_by = self.created_by.clone().or_else(|| {
+            self.created_by_tool.as_ref().map(|(name, version)| {
+                format!(
+                    "{name} version {version} (csv2parquet version {})",
+                    env!("CARGO_PKG_VERSION")
+                )
+            })
+        });
+        WriterArgs {
+            compression: self.compression.clone(),
+            encoding: self.encoding.clone(),
+            data_page_size_limit: self.data_page_size_limit,
+            dictionary_page_size_limit: self.dictionary_page_size_limit,
+            write_batch_size: self.write_batch_size,
+            max_row_group_size: if self.single_row_group {
+                Some(usize::MAX)
+            } else {
+                self.max_row_group_size
+            },
+            created_by,
+            dictionary: self.dictionary,
+            statistics: self.statistics.clone(),
+            max_statistics_size: self.max_statistics_size,
+        }
+    }
+
+    fn writer_properties_builder(&self) -> WriterPropertiesBuilder {
+        let mut props = self.writer_args().writer_properties_builder();
 
-    let output = File::create(opts.output)?;
+        if !self.metadata.is_empty() {
+            props = props.set_key_value_metadata(Some(self.key_value_metadata()));
+        }
 
-    let mut props = WriterProperties::builder().set_dictionary_enabled(opts.dictionary);
+        for (column, size) in &self.column_max_statistics_size {
+            props = props.set_column_max_statistics_size(self.column_path(column), *size);
+        }
 
-    if let Some(statistics) = opts.statistics {
-        let statistics = match statistics {
-            ParquetEnabledStatistics::Chunk => EnabledStatistics::Chunk,
-            ParquetEnabledStatistics::Page => EnabledStatistics::Page,
-            ParquetEnabledStatistics::None => EnabledStatistics::None,
-        };
+        for column in &self.no_dictionary_columns {
+            props = props.set_column_dictionary_enabled(self.column_path(column), false);
+        }
 
-        props = props.set_statistics_enabled(statistics);
+        props
     }
 
-    if let Some(compression) = opts.compression {
-        let compression = match compression {
-            ParquetCompression::UNCOMPRESSED => Compression::UNCOMPRESSED,
-            ParquetCompression::SNAPPY => Compression::SNAPPY,
-            ParquetCompression::GZIP => Compression::GZIP(GzipLevel::default()),
-            ParquetCompression::LZO => Compression::LZO,
-            ParquetCompression::BROTLI => Compression::BROTLI(BrotliLevel::default()),
-            ParquetCompression::LZ4 => Compression::LZ4,
-            ParquetCompression::ZSTD => Compression::ZSTD(ZstdLevel::default()),
-            ParquetCompression::LZ4_RAW => Compression::LZ4_RAW,
-        };
+    /// Checks that the per-column writer options name leaf columns of `schema`.
+    fn validate_column_paths(&self, schema: &Schema) -> Result<(), ParquetError> {
+        let descriptor = arrow_to_parquet_schema(schema)?;
+        let leaves: Vec<&ColumnPath> = descriptor
+            .columns()
+            .iter()
+            .map(|column| column.path())
+            .collect();
+
+        let columns = self
+            .no_dictionary_columns
+            .iter()
+            .map(|column| (column, "no_dictionary_columns"))
+            .chain(
+                self.column_max_statistics_size
+                    .iter()
+                    .map(|(column, _)| (column, "column_max_statistics_size")),
+            );
+        for (column, option) in columns {
+            if !leaves.contains(&&self.column_path(column)) {
+                return Err(ParquetError::General(format!(
+                    "Unknown column {column:?} in {option}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn column_path(&self, column: &str) -> ColumnPath {
+        match self.column_path_separator {
+            Some(separator) => ColumnPath::new(column.split(separator).map(String::from).collect()),
+            None => ColumnPath::from(column),
+        }
+    }
+
+    /// The escape byte the readers use. For `QuoteStyle::Doubling` it is the quote, which the
+    /// readers match first, so nothing is treated as an escape.
+    fn escape_byte(&self) -> u8 {
+        match self.quote_style {
+            QuoteStyle::Doubling => b'"',
+            QuoteStyle::Escape | QuoteStyle::Both => self.escape as u8,
+        }
+    }
 
-        props = props.set_compression(compression);
+    /// Whether empty string fields are told apart by quoting, as arrow reads all of them as null.
+    fn keeps_empty_strings(&self) -> bool {
+        !self.quoted_empty_as_null || !self.empty_as_null.unwrap_or(true)
     }
 
-    if let Some(encoding) = opts.encoding {
-        let encoding = match encoding {
-            ParquetEncoding::PLAIN => Encoding::PLAIN,
-            ParquetEncoding::PLAIN_DICTIONARY => Encoding::PLAIN_DICTIONARY,
-            ParquetEncoding::RLE => Encoding::RLE,
-            ParquetEncoding::RLE_DICTIONARY => Encoding::RLE_DICTIONARY,
-            ParquetEncoding::DELTA_BINARY_PACKED => Encoding::DELTA_BINARY_PACKED,
-            ParquetEncoding::DELTA_LENGTH_BYTE_ARRAY => Encoding::DELTA_LENGTH_BYTE_ARRAY,
-            ParquetEncoding::DELTA_BYTE_ARRAY => Encoding::DELTA_BYTE_ARRAY,
-            ParquetEncoding::BYTE_STREAM_SPLIT => Encoding::BYTE_STREAM_SPLIT,
+    /// The number of records in the input before the first data record.
+    fn header_records(&self) -> usize {
+        if self.header.unwrap_or(true) {
+            self.header_rows.unwrap_or(1)
+        } else {
+            0
+        }
+    }
+
+    /// Whether the type of `column` is inferred rather than read as a string.
+    fn infers_column(&self, column: &str) -> bool {
+        self.infer_only_columns.is_empty()
+            || self.infer_only_columns.iter().any(|name| name == column)
+    }
+
+    fn parquet_encoding(&self) -> Option<Encoding> {
+        self.encoding.as_ref().map(Encoding::from)
+    }
+
+    /// Describes settings that likely have no effect or don't suit the columns of `schema`, e.g.
+    /// `BYTE_STREAM_SPLIT` for string columns.
+    pub fn encoding_warnings(&self, schema: &Schema) -> Vec<String> {
+        let Some(encoding) = self.parquet_encoding() else {
+            return Vec::new();
         };
+        let mut warnings = Vec::new();
+
+        if self.dictionary && encoding != Encoding::PLAIN {
+            warnings.push(format!(
+                "Dictionary encoding is enabled, so the {encoding} encoding is only used for \
+                 columns whose dictionary grows too large"
+            ));
+        }
+
+        let suits: fn(&DataType) -> bool = match encoding {
+            Encoding::BYTE_STREAM_SPLIT => DataType::is_floating,
+            Encoding::DELTA_BINARY_PACKED => {
+                |data_type| data_type.is_integer() || data_type.is_temporal()
+            }
+            Encoding::DELTA_LENGTH_BYTE_ARRAY | Encoding::DELTA_BYTE_ARRAY => |data_type| {
+                matches!(
+                    data_type,
+                    DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary
+                )
+            },
+            Encoding::RLE => |data_type| data_type == &DataType::Boolean,
+            _ => |_| true,
+        };
+        let unsuited: Vec<&String> = schema
+            .fields()
+            .iter()
+            .filter(|field| !suits(field.data_type()))
+            .map(|field| field.name())
+            .collect();
+        if !unsuited.is_empty() {
+            warnings.push(format!(
+                "The {encoding} encoding does not suit the types of columns {unsuited:?}"
+            ));
+        }
+
+        warnings
+    }
 
-        props = props.set_encoding(encoding);
+    /// The log level matching `verbose` for frontends that configure a logger: warnings by
+    /// default, then info, debug, and trace.
+    pub fn log_level(&self) -> LevelFilter {
+        match self.verbose {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
     }
+}
+
+/// Writes record batches to a parquet file, giving control over where row groups end.
+pub struct ParquetSink<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    /// The size of the file closed without rows, its schema and metadata.
+    empty_size: usize,
+}
 
-    if let Some(size) = opts.write_batch_size {
-        props = props.set_write_batch_size(size);
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn try_new(
+        output: W,
+        schema: SchemaRef,
+        props: WriterProperties,
+    ) -> Result<Self, ParquetError> {
+        Self::try_new_with_options(
+            output,
+            schema,
+            ArrowWriterOptions::new().with_properties(props),
+        )
     }
 
-    if let Some(size) = opts.data_page_size_limit {
-        props = props.set_data_page_size_limit(size);
+    /// Creates a sink with arrow writer options, e.g. to skip the embedded arrow schema.
+    pub fn try_new_with_options(
+        output: W,
+        schema: SchemaRef,
+        options: ArrowWriterOptions,
+    ) -> Result<Self, ParquetError> {
+        let mut empty = Vec::new();
+        ArrowWriter::try_new_with_options(&mut empty, schema.clone(), options.clone())?.close()?;
+        let writer = ArrowWriter::try_new_with_options(output, schema, options)?;
+        Ok(Self {
+            writer,
+            empty_size: empty.len(),
+        })
     }
 
-    if let Some(size) = opts.dictionary_page_size_limit {
-        props = props.set_dictionary_page_size_limit(size);
+    /// Creates a sink with the writer properties from `opts`, failing if its per-column options
+    /// name columns that aren't leaf columns of `schema`.
+    pub fn try_new_with_opts(
+        output: W,
+        schema: SchemaRef,
+        opts: &Opts,
+    ) -> Result<Self, ParquetError> {
+        opts.validate_column_paths(&schema)?;
+        let options = opts.writer_options_for_schema(&schema);
+        Self::try_new_with_options(output, schema, options)
     }
 
-    if let Some(size) = opts.dictionary_page_size_limit {
-        props = props.set_dictionary_page_size_limit(size);
+    /// Buffers a batch, flushing a row group whenever the max row group size is reached.
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), ParquetError> {
+        self.writer.write(batch)
     }
 
-    if let Some(size) = opts.max_row_group_size {
-        props = props.set_max_row_group_size(size);
+    /// Returns the bytes written so far plus the estimated size of the buffered row group.
+    pub fn estimated_size(&self) -> usize {
+        self.writer.bytes_written() + self.writer.in_progress_size()
+    }
+
+    /// Guesses the bytes the last written row group adds when the file is closed: its metadata in
+    /// the footer, and about as much again for its page indexes.
+    fn last_row_group_footer_size(&self) -> Result<usize, ParquetError> {
+        let Some(row_group) = self.writer.flushed_row_groups().last() else {
+            return Ok(0);
+        };
+        let mut encoded = Vec::new();
+        row_group
+            .to_thrift()
+            .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut encoded))?;
+        Ok(2 * encoded.len())
     }
 
-    if let Some(created_by) = opts.created_by {
-        props = props.set_created_by(created_by);
+    /// Returns the number of row groups written so far.
+    pub fn flushed_row_groups(&self) -> usize {
+        self.writer.flushed_row_groups().len()
     }
 
-    if let Some(size) = opts.max_statistics_size {
-        props = props.set_max_statistics_size(size);
+    /// Returns the number of rows buffered in the current row group.
+    pub fn in_progress_rows(&self) -> usize {
+        self.writer.in_progress_rows()
     }
 
-    let mut writer = ArrowWriter::try_new(output, reader.schema(), Some(props.build()))?;
+    /// Ends the current row group, so the next batch starts a new one.
+    pub fn flush_row_group(&mut self) -> Result<(), ParquetError> {
+        self.writer.flush()
+    }
 
-    for batch in reader {
-        match batch {
-            Ok(batch) => {
-                let batch = replace_empty_strings_with_nulls(batch).unwrap();
-                writer.write(&batch)?
-            },
-            Err(error) => return Err(error.into()),
+    /// Flushes remaining data, writes the footer, and flushes the underlying writer.
+    pub fn finish(self) -> Result<FileMetaData, ParquetError> {
+        self.writer.close()
+    }
+
+    /// Flushes remaining data, writes the footer, and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W, ParquetError> {
+        self.writer.into_inner()
+    }
+}
+
+pub fn convert(mut opts: Opts) -> Result<(), ParquetError> {
+    if let Some(url) = object_store_url(&opts.output) {
+        if opts.input_dir.is_some() {
+            return Err(ParquetError::General(
+                "input_dir cannot be combined with an object store output".to_string(),
+            ));
         }
+        return convert_to_url(url.to_string(), opts);
     }
 
-    match writer.close() {
-        Ok(_) => Ok(()),
-        Err(error) => Err(error),
+    match opts.input_dir.take() {
+        Some(input_dir) => convert_dir(&input_dir, opts),
+        None => convert_impl(opts, None, None, None, None),
     }
 }
 
-fn replace_empty_strings_with_nulls(batch: RecordBatch) -> arrow::error::Result<RecordBatch> {
-    let mut new_columns: Vec<ArrayRef> = Vec::new();
+/// Converts every `.csv` file in `input_dir` to a `.parquet` file in the `opts.output` directory,
+/// writing the success marker once all of them have been converted.
+fn convert_dir(input_dir: &Path, opts: Opts) -> Result<(), ParquetError> {
+    if opts.manifest.is_some() || opts.extract_schema.is_some() {
+        return Err(ParquetError::General(
+            "manifest and extract_schema cannot be combined with input_dir".to_string(),
+        ));
+    }
 
-    // Iterate over each column in the batch
-    for i in 0..batch.num_columns() {
-        let column = batch.column(i);
-        let schema = batch.schema();
-        let field = schema.field(i);
+    let mut inputs = fs::read_dir(input_dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|error| {
+            ParquetError::General(format!(
+                "Error reading input directory: {input_dir:?}, message: {error}"
+            ))
+        })?;
+    inputs.retain(|path| {
+        path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+    });
+    inputs.sort();
 
-        // Check if the column is a nullable string type
-        if matches!(field.data_type(), &DataType::Utf8) && field.is_nullable() {
-            // Create a new column with empty strings replaced by nulls
-            let string_array = column.as_any().downcast_ref::<StringArray>().unwrap();
-            // let mut builder = LargeStringArray::into_builder(string_array.len()).unwrap();
-            let mut builder: GenericByteBuilder<GenericStringType<i32>> = GenericByteBuilder::new();
+    if inputs.is_empty() {
+        return Err(ParquetError::General(format!(
+            "No CSV files in the input directory: {input_dir:?}"
+        )));
+    }
 
-            for j in 0..string_array.len() {
-                if string_array.is_null(j) || string_array.value(j).is_empty() {
-                    builder.append_null();
-                } else {
-                    builder.append_value(string_array.value(j));
-                }
+    if opts.skip_empty_files {
+        inputs.retain(|path| {
+            let empty = fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0);
+            if empty {
+                debug!("Skipping empty input {path:?}");
             }
+            !empty
+        });
+        if inputs.is_empty() {
+            return Err(ParquetError::General(format!(
+                "Every CSV file in the input directory is empty: {input_dir:?}"
+            )));
+        }
+    }
 
-            new_columns.push(Arc::new(builder.finish()) as ArrayRef);
-        } else {
-            // For non-string or non-nullable fields, use the original column
-            new_columns.push(column.clone());
+    fs::create_dir_all(&opts.output).map_err(|error| {
+        ParquetError::General(format!(
+            "Error creating output directory: {:?}, message: {error}",
+            opts.output
+        ))
+    })?;
+
+    let mut outputs = DirOutputs {
+        events: Events::create(opts.events.as_deref())?,
+        header_map: Vec::new(),
+    };
+    for input in inputs {
+        let mut file_opts = opts.clone();
+        file_opts.output = opts
+            .output
+            .join(input.with_extension("parquet").file_name().unwrap());
+        file_opts.input = input;
+        file_opts.success_marker = false;
+
+        info!("Converting {:?} to {:?}", file_opts.input, file_opts.output);
+        let input = file_opts.input.clone();
+        convert_impl(file_opts, None, None, None, Some(&mut outputs)).map_err(|error| {
+            ParquetError::General(format!("Error converting {input:?}: {error}"))
+        })?;
+    }
+
+    if let Some(ref path) = opts.write_header_map {
+        if !opts.dry {
+            write_header_map(path, &outputs.header_map, true)?;
+            info!("Wrote header map to {path:?}");
         }
     }
 
-    // Create a new RecordBatch with updated columns
-    let new_batch = RecordBatch::try_new(batch.schema(), new_columns)?;
+    if opts.success_marker && !opts.dry && !opts.estimate {
+        write_success_marker(&opts.output)?;
+    }
 
-    Ok(new_batch)
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Converts CSV read from `reader`, e.g. a socket or a decompressor, instead of opening
+/// `opts.input`. The reader doesn't need to be seekable, its start is buffered for schema
+/// inference like piped input.
+pub fn convert_from_read<R: Read>(reader: R, opts: Opts) -> Result<(), ParquetError> {
+    convert_impl(opts, Some(Box::new(reader)), None, None, None)
+}
+
+/// A function applied to every batch before it is written, see [`convert_with_transform`].
+type Transform<'a> = dyn FnMut(RecordBatch) -> Result<RecordBatch, ArrowError> + 'a;
+
+/// Converts the input like [`convert`], passing every batch through `transform` before writing
+/// it, e.g. to add derived columns or mask values. `transform` is first called with an empty batch
+/// of the converted schema, and the schema it returns is the output schema, so it must return
+/// batches of that same schema every time.
+pub fn convert_with_transform<F>(opts: Opts, mut transform: F) -> Result<(), ParquetError>
+where
+    F: FnMut(RecordBatch) -> Result<RecordBatch, ArrowError>,
+{
+    convert_impl(opts, None, None, Some(&mut transform), None)
+}
+
+/// Converts the input like [`convert`] but returns the parquet file as bytes instead of writing
+/// it to `opts.output`, e.g. to upload it without a temporary file. Options that need the output
+/// file are not supported, and a dry run or estimate returns no bytes.
+pub fn convert_to_vec(mut opts: Opts) -> Result<Vec<u8>, ParquetError> {
+    check_no_output_file(&opts)?;
+
+    opts.buffer_in_memory = true;
+    let mut buffer = Vec::new();
+    convert_impl(
+        opts,
+        None,
+        Some(Destination::Memory(&mut buffer)),
+        None,
+        None,
+    )?;
+    Ok(buffer)
+}
+
+/// Converts the input like [`convert`] but uploads the parquet file to `location` in `store` while
+/// writing it. Options that need the output file are not supported.
+#[cfg(feature = "object_store")]
+pub fn convert_to_object_store(
+    opts: Opts,
+    store: Arc<dyn object_store::ObjectStore>,
+    location: object_store::path::Path,
+) -> Result<(), ParquetError> {
+    check_no_output_file(&opts)?;
+
+    let mut writer = object_store_output::ObjectStoreWriter::try_new(store, location)?;
+    let converted = convert_impl(
+        opts,
+        None,
+        Some(Destination::ObjectStore(&mut writer)),
+        None,
+        None,
+    );
+    // A failed, dry, or estimating run leaves the upload unfinished.
+    writer.abort()?;
+    converted
+}
+
+/// Returns the output if it is a URL with a scheme, like `s3://bucket/key.parquet`.
+fn object_store_url(output: &Path) -> Option<&str> {
+    output.to_str().filter(|output| {
+        output.split_once("://").is_some_and(|(scheme, _)| {
+            scheme.len() > 1
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        })
+    })
+}
+
+#[cfg(feature = "object_store")]
+fn convert_to_url(url: String, opts: Opts) -> Result<(), ParquetError> {
+    let parsed = url::Url::parse(&url)
+        .map_err(|error| ParquetError::General(format!("Invalid output URL {url:?}: {error}")))?;
+    let options = std::env::vars()
+        .filter(|(key, _)| {
+            ["AWS_", "GOOGLE_", "AZURE_"]
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        })
+        .map(|(key, value)| (key.to_ascii_lowercase(), value));
+    let (store, location) = object_store::parse_url_opts(&parsed, options).map_err(|error| {
+        ParquetError::General(format!("Error opening object store for {url:?}: {error}"))
+    })?;
+
+    info!("Uploading to {url}");
+    convert_to_object_store(opts, Arc::from(store), location)
+}
+
+#[cfg(not(feature = "object_store"))]
+fn convert_to_url(url: String, _opts: Opts) -> Result<(), ParquetError> {
+    Err(ParquetError::General(format!(
+        "Writing to {url:?} requires the object_store feature"
+    )))
+}
+
+fn check_no_output_file(opts: &Opts) -> Result<(), ParquetError> {
+    if opts.temp_dir.is_some()
+        || opts.manifest.is_some()
+        || opts.verify_after_write
+        || opts.success_marker
+        || opts.content_hash
+        || opts.omit_stats_for_null_columns
+    {
+        return Err(ParquetError::General(
+            "temp_dir, manifest, verify_after_write, success_marker, content_hash, and \
+             omit_stats_for_null_columns need an output file"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Where [`convert_impl`] writes the parquet file instead of the output file.
+enum Destination<'a> {
+    Memory(&'a mut Vec<u8>),
+    #[cfg(feature = "object_store")]
+    ObjectStore(&'a mut object_store_output::ObjectStoreWriter),
+}
+
+/// Runs a conversion, writing to `destination` instead of the output file if given.
+///
+/// The conversion runs in stages: the input is opened and prepared, the schema is inferred and
+/// the input pre-scanned once for everything else the options need from it, the read is planned,
+/// and the batches are written and the output post-processed.
+fn convert_impl<'a>(
+    mut opts: Opts,
+    source: Option<Box<dyn Read + 'a>>,
+    destination: Option<Destination<'_>>,
+    mut transform: Option<&mut Transform<'_>>,
+    mut dir: Option<&mut DirOutputs>,
+) -> Result<(), ParquetError> {
+    if opts.input_dir.is_some() {
+        return Err(ParquetError::General(
+            "input_dir is only supported when converting files".to_string(),
+        ));
+    }
+
+    if let Some(path) = opts.metadata_file.take() {
+        let mut metadata = read_metadata_file(&path)?;
+        metadata.retain(|(key, _)| !opts.metadata.iter().any(|(inline, _)| inline == key));
+        metadata.append(&mut opts.metadata);
+        opts.metadata = metadata;
+    }
+    if !opts.geometry_columns.is_empty() && opts.metadata.iter().any(|(key, _)| key == "geo") {
+        return Err(ParquetError::General(
+            "The geo metadata key is written for geometry_columns and cannot also be set in metadata"
+                .to_string(),
+        ));
+    }
+
+    let from_reader = source.is_some();
+    let mut archive = None;
+    let input = open_input(&opts, source, &mut archive)?;
+
+    let schema = match (opts.schema.take(), opts.schema_spec.take()) {
+        (Some(_), Some(_)) => Err(ParquetError::General(
+            "Only one of schema and schema_spec can be set".to_string(),
+        )),
+        (Some(schema), None) => Ok(Some(schema)),
+        (None, Some(spec)) => parse_schema_spec(&spec).map(Some),
+        (None, None) => Ok(None),
+    }?;
+
+    let mut input = prepare_input(input, &mut opts, schema.as_ref())?;
+
+    if opts.count_only {
+        let mut rows = count_records(&mut input.reader, &opts)?;
+        if let Some((start, end)) = opts.row_range {
+            rows = rows.min(end).saturating_sub(start);
+        }
+        debug!("Counted {rows} data rows");
+        info!("Row count:");
+        println!("{rows}");
+        return Ok(());
+    }
+
+    let (schema, inferred_records, scan) = resolve_schema(&mut input, schema, &opts)?;
+    let schema = annotate_schema(schema, &scan, &opts)?;
+    if report_schema(&schema, &opts)? {
+        return Ok(());
+    }
+
+    plan_row_groups(&mut opts, &scan)?;
+    let plan = plan_read(schema, &scan, &mut opts)?;
+
+    if let Some(ref path) = opts.write_header_map {
+        let file = dir.is_some().then(|| {
+            opts.input
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        });
+        let sources: Vec<_> = if opts.header.unwrap_or(true) {
+            scan.header().iter().cloned().map(Some).collect()
+        } else {
+            vec![None; plan.read_schema.fields().len()]
+        };
+        let entries = sources
+            .into_iter()
+            .zip(plan.read_schema.fields())
+            .map(|(source, field)| HeaderMapEntry {
+                file: file.clone(),
+                source,
+                column: field.name().clone(),
+            });
+        match dir {
+            Some(ref mut dir) => dir.header_map.extend(entries),
+            None => {
+                write_header_map(path, &entries.collect::<Vec<_>>(), false)?;
+                info!("Wrote header map to {path:?}");
+            }
+        }
+    }
+    let reader = read_batches(input, &plan, &opts)?;
+
+    opts.validate_column_paths(&plan.schema)?;
+
+    if opts.verify_sorted && !opts.first_column_is_key {
+        return Err(ParquetError::General(
+            "verify_sorted requires a sort column declared with first_column_is_key".to_string(),
+        ));
+    }
+
+    let pipeline = BatchPipeline::try_new(plan.schema, plan.projection, &opts)?;
+    // Columns that nulls are filled in for are written as non-null.
+    let schema = pipeline.schema.clone();
+
+    if opts.estimate {
+        let props = opts.writer_properties_for_schema(&schema);
+        let estimate = estimate_output_size(reader, &pipeline, props)?;
+        info!("Estimated output size:");
+        println!("{estimate}");
+        return Ok(());
+    }
+
+    let schema = match transform {
+        Some(ref mut transform) => transform(RecordBatch::new_empty(schema))?.schema(),
+        None => schema,
+    };
+
+    for warning in opts.encoding_warnings(&schema) {
+        warn!("{warning}");
+    }
+
+    let options = opts.writer_options(opts.writer_properties_for_schema(&schema));
+    let batches = Batches {
+        reader,
+        pipeline,
+        transform,
+        schema,
+    };
+
+    #[cfg(feature = "interrupt")]
+    let _interrupt_guard = opts
+        .finalize_on_interrupt
+        .then(interrupt::InterruptGuard::install);
+
+    let mut file_events;
+    let events = match dir {
+        Some(dir) => &mut dir.events,
+        None => {
+            file_events = Events::create(opts.events.as_deref())?;
+            &mut file_events
+        }
+    };
+
+    if let Some(destination) = destination {
+        return write_to_destination(destination, options, batches, &opts, events);
+    }
+
+    // The temporary file is removed when dropped unless it was moved to the output.
+    let temp_path = match opts.temp_dir {
+        Some(ref temp_dir) => Some(
+            NamedTempFile::new_in(temp_dir)
+                .map_err(|error| {
+                    ParquetError::General(format!(
+                        "Error creating temporary file in {temp_dir:?}, message: {error}"
+                    ))
+                })?
+                .into_temp_path(),
+        ),
+        None => None,
+    };
+    let write_path = temp_path.as_deref().unwrap_or(&opts.output).to_path_buf();
+    let schema = batches.schema.clone();
+    let written = write_file(&write_path, options, batches, &opts, events)?;
+    finish_file(
+        &write_path,
+        written,
+        &schema,
+        inferred_records,
+        from_reader,
+        &opts,
+    )
+}
+
+/// The input of a conversion, read from the start.
+struct Input<'a> {
+    reader: Box<dyn SeekRead + 'a>,
+    /// Set when inference must stop short of the end of the buffered input.
+    infer_limit: Option<usize>,
+    /// Whether the whole input can be rewound, rather than only what was buffered for inference.
+    rewindable: bool,
+    /// What the record scanners find, if they were placed before records are rewritten.
+    scans: Option<RecordScans>,
+}
+
+impl<'a> Input<'a> {
+    /// Buffers a non-seekable reader for schema inference, see [`buffer_input`].
+    fn buffered<R: Read + 'a>(reader: R, opts: &Opts) -> Self {
+        let (reader, infer_limit) = buffer_input(reader, opts);
+        Self {
+            reader,
+            infer_limit,
+            rewindable: false,
+            scans: None,
+        }
+    }
+
+    /// The part of the input that schema inference reads.
+    fn sample(&mut self) -> Box<dyn Read + '_> {
+        match self.infer_limit {
+            Some(limit) => Box::new((&mut self.reader).take(limit as u64)),
+            None => Box::new(&mut self.reader),
+        }
+    }
+}
+
+/// Opens the input file, or buffers `source` if given, decompressing it and transcoding UTF-16
+/// input. Entries of zip archives borrow from `archive`.
+fn open_input<'a>(
+    opts: &Opts,
+    source: Option<Box<dyn Read + 'a>>,
+    archive: &'a mut Option<ZipArchive<File>>,
+) -> Result<Input<'a>, ParquetError> {
+    let mut input = if let Some(reader) = source {
+        Input::buffered(reader, opts)
+    } else {
+        let mut file = File::open(&opts.input)?;
+
+        // Pipes, FIFOs, and character devices cannot be rewound reliably, so only regular files
+        // are read directly and everything else is buffered for schema inference.
+        let is_regular_file = !opts.force_streaming && file.metadata()?.file_type().is_file();
+        if opts.force_streaming {
+            debug!("Buffering the input for schema inference instead of rewinding it");
+        } else if !is_regular_file {
+            debug!("Input is not a regular file, buffering it for schema inference");
+        }
+
+        let is_zip = opts
+            .input
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+
+        if is_zip {
+            let archive = archive.insert(ZipArchive::new(file).map_err(|error| {
+                ParquetError::General(format!("Error reading zip archive: {error}"))
+            })?);
+            let name = zip_csv_entry(archive, opts.zip_entry.as_deref())?;
+            debug!("Reading {name:?} from zip archive");
+            let entry = archive.by_name(&name).map_err(|error| {
+                ParquetError::General(format!("Error reading zip entry {name:?}: {error}"))
+            })?;
+
+            Input::buffered(entry, opts)
+        } else if let Some(compression) =
+            InputCompression::detect(&opts.input, &mut file, is_regular_file)?
+        {
+            debug!("Decompressing {compression:?} input");
+            Input::buffered(compression.decoder(file)?, opts)
+        } else if is_regular_file && file.rewind().is_ok() {
+            Input {
+                reader: Box::new(file),
+                infer_limit: None,
+                rewindable: true,
+                scans: None,
+            }
+        } else {
+            Input::buffered(file, opts)
+        }
+    };
+
+    // UTF-16 input is recognized by its byte order mark and transcoded before parsing.
+    let mut bom = Vec::with_capacity(2);
+    (&mut input.reader).take(2).read_to_end(&mut bom)?;
+    input.reader.rewind()?;
+    if bom == [0xFF, 0xFE] || bom == [0xFE, 0xFF] {
+        debug!("Input starts with a UTF-16 byte order mark, transcoding it to UTF-8");
+        input = Input::buffered(utf16_decoder(input.reader, &bom)?, opts);
+    }
+
+    Ok(input)
+}
+
+/// Normalizes the input and sniffs its dialect, then combines several header rows and fixes
+/// ragged rows as the options require. The expected row width comes from `schema` if given.
+/// Records are scanned before they are rewritten, see [`scan_records`].
+fn prepare_input<'a>(
+    mut input: Input<'a>,
+    opts: &mut Opts,
+    schema: Option<&Schema>,
+) -> Result<Input<'a>, ParquetError> {
+    if opts.normalize_newlines {
+        let normalized = NewlineNormalizer::new(input.reader, opts.escape_byte());
+        input = Input::buffered(normalized, opts);
+    }
+
+    if opts.detect_delimiter || opts.sniff_dialect {
+        // Stay within what was buffered for inference so the input can still be rewound.
+        let lines = opts
+            .max_read_records
+            .map_or(SNIFF_LINES, |records| SNIFF_LINES.min(records + 1));
+        let bytes = input
+            .infer_limit
+            .map_or(SNIFF_BYTES, |limit| SNIFF_BYTES.min(limit));
+        let sample = read_sample(&mut input.reader, lines, bytes)?;
+        input.reader.rewind()?;
+
+        match detect_delimiter(&sample, opts.escape_byte()) {
+            Some(delimiter) => {
+                info!("Detected delimiter {:?}", delimiter as char);
+                opts.delimiter = delimiter as char;
+            }
+            None => debug!("Could not detect the delimiter, using {:?}", opts.delimiter),
+        }
+
+        if opts.sniff_dialect {
+            if opts.header.is_none() {
+                opts.header = detect_header(&sample, opts.delimiter as u8, opts.escape_byte());
+            }
+            info!(
+                "Sniffed dialect: delimiter {:?}, quote '\"', header {}",
+                opts.delimiter,
+                opts.header.unwrap_or(true)
+            );
+        }
+    }
+
+    // The CSV readers cannot turn off doubled quotes, so they read escaped quotes rewritten.
+    if let QuoteStyle::Escape = opts.quote_style {
+        let normalized =
+            QuoteNormalizer::new(input.reader, opts.delimiter as u8, opts.escape_byte());
+        input = Input::buffered(normalized, opts);
+        opts.quote_style = QuoteStyle::Doubling;
+    }
+
+    match opts.header_rows {
+        Some(0) => {
+            return Err(ParquetError::General(
+                "header_rows must be at least 1, set header to false for files without a header"
+                    .to_string(),
+            ))
+        }
+        Some(rows) if rows > 1 && opts.header == Some(false) => {
+            return Err(ParquetError::General(
+                "header_rows cannot be combined with header set to false".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    let header_rows = opts.header_rows.filter(|&rows| rows > 1);
+    let ragged = !matches!(opts.on_ragged, RaggedMode::Fail) || opts.ignore_trailing_delimiter;
+    if header_rows.is_none() && !ragged {
+        return Ok(input);
+    }
+
+    // Both rewrite the records, so the scanners look at them before that.
+    let scans = RecordScans::default();
+    let mut reader = scan_records(input.reader, opts, &scans);
+    if let Some(rows) = header_rows {
+        reader = Box::new(HeaderRowsReader::new(
+            reader,
+            opts.delimiter as u8,
+            opts.escape_byte(),
+            rows,
+            &opts.header_separator,
+        ));
+    }
+    if ragged {
+        // The expected width comes from the schema if we have one, the first row otherwise.
+        reader = Box::new(
+            RaggedReader::new(
+                reader,
+                opts.delimiter as u8,
+                opts.escape_byte(),
+                &opts.on_ragged,
+                schema.map(|schema| schema.fields().len()),
+                opts.ignore_trailing_delimiter,
+            )
+            .record_padding(
+                usize::from(opts.header.unwrap_or(true)),
+                opts.keeps_empty_strings().then(|| scans.padded.clone()),
+            ),
+        );
+    }
+
+    let mut input = Input::buffered(reader, opts);
+    input.scans = Some(scans);
+    Ok(input)
+}
+
+/// Infers the schema unless one is given and pre-scans the input, adjusting the inferred types as
+/// the options require. Returns the schema, the number of records inference read if it ran, and
+/// what the pre-scan found. The input is rewound afterwards.
+fn resolve_schema(
+    input: &mut Input,
+    schema: Option<Schema>,
+    opts: &Opts,
+) -> Result<(Schema, Option<usize>, PreScan), ParquetError> {
+    if opts.reorder_to_schema && (schema.is_none() || opts.header == Some(false)) {
+        return Err(ParquetError::General(
+            "Reordering to the schema requires a schema and a CSV header".to_string(),
+        ));
+    }
+    if opts.match_schema_to_header
+        && (schema.is_none() || opts.header == Some(false) || opts.reorder_to_schema)
+    {
+        return Err(ParquetError::General(
+            "Matching the schema to the header requires a schema and a CSV header, and cannot be combined with reorder_to_schema"
+                .to_string(),
+        ));
+    }
+
+    let (schema, inferred_records) = match schema {
+        Some(schema) => (schema, None),
+        None => {
+            let format = Format::default()
+                .with_header(opts.header.unwrap_or(true))
+                .with_delimiter(opts.delimiter as u8)
+                .with_escape(opts.escape_byte())
+                .with_quote(b'"');
+            let (schema, size) = infer_schema(&format, input.sample(), opts).map_err(|error| {
+                ParquetError::General(format!("Error inferring schema: {error}"))
+            })?;
+            debug!("Inferred schema from {size} records");
+
+            // Inference consumed the input, go back to the start.
+            input.reader.rewind()?;
+            (
+                dedup_field_names(schema, opts.dedup_header_names)?,
+                Some(size),
+            )
+        }
+    };
+
+    let scan = pre_scan(input, opts, inferred_records.and(Some(&schema)))?;
+    let schema = match inferred_records {
+        Some(_) => adjust_inferred_schema(schema, &scan, opts)?,
+        None => schema,
+    };
+
+    Ok((schema, inferred_records, scan))
+}
+
+/// Adjusts the types arrow inferred with the sampled values and the type options.
+fn adjust_inferred_schema(
+    schema: Schema,
+    scan: &PreScan,
+    opts: &Opts,
+) -> Result<Schema, ParquetError> {
+    let schema = match opts.type_priority {
+        Some(ref priority) if opts.max_read_records != Some(0) => {
+            prioritize_types(&schema, scan, priority, opts)?
+        }
+        _ => schema,
+    };
+
+    let schema = if (opts.lenient_integers || opts.large_int_as_decimal)
+        && opts.max_read_records != Some(0)
+    {
+        integer_columns(&schema, scan, opts)
+    } else {
+        schema
+    };
+
+    if opts.fail_on_all_string
+        && opts.max_read_records != Some(0)
+        && schema.fields().len() > 1
+        && schema
+            .fields()
+            .iter()
+            .all(|field| field.data_type() == &DataType::Utf8)
+    {
+        return Err(ParquetError::General(format!(
+            "Inferred all {} columns as strings, check the delimiter and other settings",
+            schema.fields().len()
+        )));
+    }
+
+    let schema = match opts.timestamp_unit {
+        Some(ref unit) => Schema::new_with_metadata(
+            schema
+                .fields()
+                .iter()
+                .map(|field| match field.data_type() {
+                    DataType::Timestamp(_, tz) => field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(DataType::Timestamp(*unit, tz.clone())),
+                    _ => field.as_ref().clone(),
+                })
+                .collect::<Vec<_>>(),
+            schema.metadata().clone(),
+        ),
+        None => schema,
+    };
+
+    // Without any records to look at, only the column names are known and every column is read
+    // as a string.
+    let schema = if opts.max_read_records == Some(0) {
+        Schema::new(
+            schema
+                .fields()
+                .iter()
+                .map(|field| Field::new(field.name(), DataType::Utf8, true))
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        schema
+    };
+
+    validate_columns(&schema, &opts.string_columns, "string_columns")?;
+    validate_columns(&schema, &opts.infer_only_columns, "infer_only_columns")?;
+    validate_columns(&schema, &opts.float32_columns, "float32_columns")?;
+    let schema = Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                if opts.string_columns.contains(field.name()) || !opts.infers_column(field.name()) {
+                    field.as_ref().clone().with_data_type(DataType::Utf8)
+                } else if opts.float32_columns.contains(field.name()) {
+                    field.as_ref().clone().with_data_type(DataType::Float32)
+                } else if opts
+                    .column_number_format
+                    .iter()
+                    .any(|(column, _)| column == field.name())
+                {
+                    field.as_ref().clone().with_data_type(DataType::Float64)
+                } else {
+                    field.as_ref().clone()
+                }
+            })
+            .collect::<Vec<_>>(),
+        schema.metadata().clone(),
+    );
+
+    let width_columns: Vec<String> = opts
+        .column_integer_width
+        .iter()
+        .map(|(column, _)| column.clone())
+        .collect();
+    validate_columns(&schema, &width_columns, "column_integer_width")?;
+    validate_columns(&schema, &opts.unsigned_columns, "unsigned_columns")?;
+    Ok(Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let width = opts
+                    .column_integer_width
+                    .iter()
+                    .find(|(column, _)| column == field.name())
+                    .map(|(_, width)| *width);
+                let unsigned = opts.unsigned_columns.contains(field.name());
+                if (width.is_some() || unsigned) && !field.data_type().is_integer() {
+                    return Err(ParquetError::General(format!(
+                        "Cannot set the integer type of column {:?}, it is inferred as {}",
+                        field.name(),
+                        field.data_type()
+                    )));
+                }
+
+                let data_type = match width.or(opts.integer_width) {
+                    Some(_) if !field.data_type().is_integer() => field.data_type().clone(),
+                    Some(IntWidth::I8) => DataType::Int8,
+                    Some(IntWidth::I16) => DataType::Int16,
+                    Some(IntWidth::I32) => DataType::Int32,
+                    Some(IntWidth::I64) | None => field.data_type().clone(),
+                };
+                let data_type = match data_type {
+                    DataType::Int8 if unsigned => DataType::UInt8,
+                    DataType::Int16 if unsigned => DataType::UInt16,
+                    DataType::Int32 if unsigned => DataType::UInt32,
+                    DataType::Int64 if unsigned => DataType::UInt64,
+                    data_type => data_type,
+                };
+                Ok(field.as_ref().clone().with_data_type(data_type))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        schema.metadata().clone(),
+    ))
+}
+
+/// Matches the schema to the CSV header if asked to, then applies the per-column options:
+/// non-null columns, physical types, geometry columns, field metadata, and descriptions.
+fn annotate_schema(schema: Schema, scan: &PreScan, opts: &Opts) -> Result<Schema, ParquetError> {
+    let schema = if opts.match_schema_to_header {
+        let (schema, _) = reorder_schema(&schema, scan.header())?;
+        debug!("Reordered the schema to the CSV header");
+        schema
+    } else {
+        schema
+    };
+
+    validate_columns(&schema, &opts.non_null_columns, "non_null_columns")?;
+    let metadata_columns: Vec<String> = opts
+        .field_metadata
+        .iter()
+        .map(|(column, _, _)| column.clone())
+        .collect();
+    validate_columns(&schema, &metadata_columns, "field_metadata")?;
+    let description_columns: Vec<String> = opts
+        .column_descriptions
+        .iter()
+        .map(|(column, _)| column.clone())
+        .collect();
+    validate_columns(&schema, &description_columns, "column_descriptions")?;
+    validate_columns(&schema, &opts.geometry_columns, "geometry_columns")?;
+    let physical_columns: Vec<String> = opts
+        .physical_types
+        .iter()
+        .map(|(column, _)| column.clone())
+        .collect();
+    validate_columns(&schema, &physical_columns, "physical_types")?;
+    let schema = Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                match opts
+                    .physical_types
+                    .iter()
+                    .find(|(column, _)| column == field.name())
+                {
+                    Some((_, physical_type)) => {
+                        let physical_type = physical_type.to_uppercase().parse()?;
+                        let data_type = data_type_for_physical(field, physical_type)?;
+                        Ok(field.as_ref().clone().with_data_type(data_type))
+                    }
+                    None => Ok(field.as_ref().clone()),
+                }
+            })
+            .collect::<Result<Vec<_>, ParquetError>>()?,
+        schema.metadata().clone(),
+    );
+    Ok(Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let mut field = field.as_ref().clone();
+                if opts.non_null_columns.contains(field.name()) {
+                    field = field.with_nullable(false);
+                }
+                if opts.geometry_columns.contains(field.name()) {
+                    field = field.with_data_type(DataType::Binary);
+                }
+
+                let mut metadata = field.metadata().clone();
+                for (_, key, value) in opts
+                    .field_metadata
+                    .iter()
+                    .filter(|(column, _, _)| column == field.name())
+                {
+                    metadata.insert(key.clone(), value.clone());
+                }
+                for (_, description) in opts
+                    .column_descriptions
+                    .iter()
+                    .filter(|(column, _)| column == field.name())
+                {
+                    metadata.insert("description".to_string(), description.clone());
+                }
+                field.with_metadata(metadata)
+            })
+            .collect::<Vec<_>>(),
+        schema.metadata().clone(),
+    ))
+}
+
+/// Compares the schema to the baseline, then prints or extracts it. Returns whether the
+/// conversion is done because only the schema was asked for.
+fn report_schema(schema: &Schema, opts: &Opts) -> Result<bool, ParquetError> {
+    if let Some(ref path) = opts.baseline_schema {
+        let drift = schema_drift(&validate_schema_file(path)?, schema);
+        if opts.fail_on_schema_drift && !drift.is_empty() {
+            return Err(ParquetError::General(format!(
+                "The schema differs from the baseline {path:?}: {}",
+                drift.join(", ")
+            )));
+        }
+        for change in &drift {
+            warn!("Schema drift from the baseline: {change}");
+        }
+    }
+
+    if opts.print_schema || opts.dry {
+        let json = schema_json(schema, opts.schema_json_compact);
+        info!("Schema:");
+        println!("{json}");
+        if opts.dry {
+            return Ok(true);
+        }
+    }
+
+    if let Some(ref path) = opts.extract_schema {
+        let schema_file = File::create(path).map_err(|error| {
+            ParquetError::General(format!(
+                "Error creating schema file: {path:?}, message: {error}"
+            ))
+        })?;
+        serde_json::to_writer_pretty(schema_file, schema).map_err(|error| {
+            ParquetError::General(format!("Error writing schema json: {error}"))
+        })?;
+        info!("Wrote schema to {path:?}");
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Checks the row group options and sets `max_row_group_size` from `target_row_groups` and the
+/// number of records the pre-scan counted. Without a count, the output is rewritten into row
+/// groups of the target size after writing, see [`rewrite_output`].
+fn plan_row_groups(opts: &mut Opts, scan: &PreScan) -> Result<(), ParquetError> {
+    if opts.single_row_group
+        && (opts.max_row_group_size.is_some()
+            || opts.target_row_groups.is_some()
+            || opts.row_group_memory_budget.is_some())
+    {
+        return Err(ParquetError::General(
+            "single_row_group cannot be combined with max_row_group_size, target_row_groups, or row_group_memory_budget"
+                .to_string(),
+        ));
+    }
+
+    if let Some(row_groups) = opts.target_row_groups {
+        if row_groups == 0 || opts.max_row_group_size.is_some() {
+            return Err(ParquetError::General(
+                "target_row_groups must be at least 1 and cannot be combined with max_row_group_size"
+                    .to_string(),
+            ));
+        }
+
+        let Some(mut rows) = scan.records else {
+            debug!(
+                "Cannot count the rows ahead, rewriting into {row_groups} row groups after writing"
+            );
+            return Ok(());
+        };
+        if let Some((start, end)) = opts.row_range {
+            rows = rows.min(end).saturating_sub(start);
+        }
+
+        let size = rows.div_ceil(row_groups).max(1);
+        debug!("Counted {rows} rows, writing row groups of up to {size} rows");
+        opts.max_row_group_size = Some(size);
+    }
+
+    Ok(())
+}
+
+/// How the CSV is read and turned into the output columns, see [`plan_read`].
+struct ReadPlan {
+    /// The output schema, before the batch pipeline.
+    schema: SchemaRef,
+    /// The schema the CSV is read with, in CSV column order.
+    read_schema: SchemaRef,
+    /// The read columns in output order, if that differs from the CSV order.
+    projection: Option<Vec<usize>>,
+}
+
+/// Plans reading the CSV into `schema`: the types columns are read with, their order, the columns
+/// that are too large for dictionary encoding, and the line number column.
+fn plan_read(schema: Schema, scan: &PreScan, opts: &mut Opts) -> Result<ReadPlan, ParquetError> {
+    let schema = Arc::new(schema);
+
+    // Columns are read as nullable so nulls in non-nullable columns can be reported clearly, and
+    // integer columns if lenient, decimal columns if rounded, geometry columns, and columns with
+    // a number format are read as strings and parsed after reading.
+    let read_schema = Arc::new(Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let field = field.as_ref().clone().with_nullable(true);
+                let is_decimal = matches!(
+                    field.data_type(),
+                    DataType::Decimal128(_, _) | DataType::Decimal256(_, _)
+                );
+                if (opts.lenient_integers && field.data_type().is_integer())
+                    || (opts.decimal_rounding.is_some() && is_decimal)
+                    || opts.geometry_columns.contains(field.name())
+                    || opts
+                        .column_number_format
+                        .iter()
+                        .any(|(column, _)| column == field.name())
+                {
+                    field.with_data_type(DataType::Utf8)
+                } else {
+                    field
+                }
+            })
+            .collect::<Vec<_>>(),
+        schema.metadata().clone(),
+    ));
+
+    // The CSV is read in header order and each batch projected to the order of the schema.
+    let (read_schema, projection) = if opts.reorder_to_schema {
+        let (read_schema, projection) = reorder_schema(&read_schema, scan.header())?;
+        (Arc::new(read_schema), Some(projection))
+    } else {
+        (read_schema, None)
+    };
+
+    if let Some(threshold) = opts.no_dictionary_for_large_columns {
+        // The read schema has the columns in CSV order.
+        for (field, length) in read_schema.fields().iter().zip(scan.average_lengths()) {
+            if length > threshold as f64 && !opts.no_dictionary_columns.contains(field.name()) {
+                debug!(
+                    "Not dictionary encoding column {:?} with values of {length:.0} bytes on average",
+                    field.name()
+                );
+                opts.no_dictionary_columns.push(field.name().clone());
+            }
+        }
+    }
+
+    let (schema, projection) = if opts.output_column_order.is_empty() {
+        (schema, projection)
+    } else {
+        validate_columns(&schema, &opts.output_column_order, "output_column_order")?;
+        let indices = opts
+            .output_column_order
+            .iter()
+            .map(|column| schema.index_of(column).unwrap())
+            .collect::<Vec<_>>();
+        if indices.len() != schema.fields().len()
+            || indices.iter().collect::<HashSet<_>>().len() != indices.len()
+        {
+            return Err(ParquetError::General(
+                "output_column_order must list every column exactly once".to_string(),
+            ));
+        }
+
+        let output = Schema::new_with_metadata(
+            indices
+                .iter()
+                .map(|&index| schema.field(index).clone())
+                .collect::<Vec<_>>(),
+            schema.metadata().clone(),
+        );
+        let projection = indices
+            .iter()
+            .map(|&index| {
+                projection
+                    .as_ref()
+                    .map_or(index, |projection| projection[index])
+            })
+            .collect();
+        (Arc::new(output), Some(projection))
+    };
+
+    // Line numbers are appended to each batch after reading, so they are the last column both
+    // before and after the projection.
+    let (schema, projection) = if opts.preserve_line_numbers {
+        if schema.field_with_name(LINE_COLUMN).is_ok() {
+            return Err(ParquetError::General(format!(
+                "Cannot add line numbers, the input already has a column named {LINE_COLUMN:?}"
+            )));
+        }
+
+        let mut fields = schema.fields().to_vec();
+        fields.push(Arc::new(Field::new(LINE_COLUMN, DataType::UInt64, false)));
+        let projection = projection.map(|mut projection| {
+            projection.push(read_schema.fields().len());
+            projection
+        });
+        (
+            Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+            projection,
+        )
+    } else {
+        (schema, projection)
+    };
+
+    Ok(ReadPlan {
+        schema,
+        read_schema,
+        projection,
+    })
+}
+
+/// What the scanners added by [`scan_records`] find in the records.
+#[derive(Default)]
+struct RecordScans {
+    quoted_empty: Rc<RefCell<BTreeSet<(usize, usize)>>>,
+    line_starts: Rc<RefCell<VecDeque<u64>>>,
+    /// The first padded column of each padded data row, see [`RaggedReader::record_padding`].
+    padded: Rc<RefCell<BTreeMap<usize, usize>>>,
+}
+
+/// Adds the scanners the options need to the CSV input, recording into `scans`. Arrow reads every
+/// empty string field as null, so quoted empty fields are located in the input to tell them apart
+/// if they are to be read differently. The scanners must see the records as they are in the input,
+/// before rewriting them changes their quoting or lines.
+fn scan_records<'a>(
+    reader: impl Read + 'a,
+    opts: &Opts,
+    scans: &RecordScans,
+) -> Box<dyn Read + 'a> {
+    let reader: Box<dyn Read + 'a> = if opts.keeps_empty_strings() {
+        Box::new(QuotedEmptyScanner::new(
+            reader,
+            opts.delimiter as u8,
+            opts.escape_byte(),
+            opts.header_records(),
+            scans.quoted_empty.clone(),
+        ))
+    } else {
+        Box::new(reader)
+    };
+    let reader: Box<dyn Read + 'a> = if opts.strict_line_endings {
+        Box::new(LineEndingCheck::new(reader, opts.escape_byte()))
+    } else {
+        reader
+    };
+    if opts.preserve_line_numbers {
+        Box::new(LineNumberScanner::new(
+            reader,
+            opts.escape_byte(),
+            scans.line_starts.clone(),
+        ))
+    } else {
+        reader
+    }
+}
+
+/// The batches read from a CSV input.
+type BatchReader<'a> = Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>> + 'a>;
+
+/// Reads the input as planned, scanning the records unless that already happened while preparing
+/// the input, and keeps only the rows in `row_range`.
+fn read_batches<'a>(
+    input: Input<'a>,
+    plan: &ReadPlan,
+    opts: &Opts,
+) -> Result<BatchReader<'a>, ParquetError> {
+    let builder = ReaderBuilder::new(plan.read_schema.clone())
+        .with_header(opts.header.unwrap_or(true))
+        .with_delimiter(opts.delimiter as u8)
+        .with_escape(opts.escape_byte())
+        .with_quote(b'"');
+
+    let Input { reader, scans, .. } = input;
+    let (input, scans): (Box<dyn Read + 'a>, _) = match scans {
+        Some(scans) => (Box::new(reader), scans),
+        None => {
+            let scans = RecordScans::default();
+            (scan_records(reader, opts, &scans), scans)
+        }
+    };
+    let string_columns = if opts.keeps_empty_strings() {
+        plan.read_schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                plan.schema
+                    .field_with_name(field.name())
+                    .is_ok_and(|field| field.data_type() == &DataType::Utf8)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let reader = builder.build(input)?;
+    let reader = EmptyFields {
+        batches: reader,
+        columns: string_columns,
+        quoted_empty: scans.quoted_empty,
+        padded: scans.padded,
+        quoted_empty_as_null: opts.quoted_empty_as_null,
+        empty_as_null: opts.empty_as_null.unwrap_or(true),
+        offset: 0,
+    };
+    let reader = LineNumbers {
+        batches: reader,
+        line_starts: opts.preserve_line_numbers.then_some(scans.line_starts),
+        header_records: opts.header_records(),
+    };
+
+    match opts.row_range {
+        Some((start, end)) if start > end => Err(ParquetError::General(format!(
+            "Invalid row_range {start}..{end}, the start is after the end"
+        ))),
+        Some((start, end)) => Ok(Box::new(RowRange::new(reader, start, end))),
+        None => Ok(Box::new(RowRange::new(reader, 0, usize::MAX))),
+    }
+}
+
+/// The read batches and what turns them into the written batches, see [`write_batches`].
+struct Batches<'a, 't, 'f> {
+    reader: BatchReader<'a>,
+    pipeline: BatchPipeline,
+    transform: Option<&'t mut Transform<'f>>,
+    /// The schema of the written batches.
+    schema: SchemaRef,
+}
+
+/// Writes the batches to `destination`, see [`convert_to_vec`] and [`convert_to_object_store`].
+fn write_to_destination(
+    destination: Destination<'_>,
+    options: ArrowWriterOptions,
+    batches: Batches,
+    opts: &Opts,
+    events: &mut Events,
+) -> Result<(), ParquetError> {
+    if needs_rechunking(opts) {
+        return Err(ParquetError::General(
+            "target_row_groups needs an output file when the input cannot be rewound".to_string(),
+        ));
+    }
+
+    let Batches {
+        reader,
+        pipeline,
+        transform,
+        schema,
+    } = batches;
+    let (metadata, timings, output_bytes, target) = match destination {
+        Destination::Memory(buffer) => {
+            let sink = ParquetSink::try_new_with_options(&mut *buffer, schema, options)?;
+            let (metadata, timings) =
+                write_batches(sink, reader, &pipeline, transform, opts, events)?;
+            (metadata, timings, buffer.len(), "memory".to_string())
+        }
+        #[cfg(feature = "object_store")]
+        Destination::ObjectStore(writer) => {
+            let sink = ParquetSink::try_new_with_options(&mut *writer, schema, options)?;
+            let (metadata, timings) =
+                write_batches(sink, reader, &pipeline, transform, opts, events)?;
+            writer.finish()?;
+            let target = writer.location().to_string();
+            (metadata, timings, writer.bytes_written(), target)
+        }
+    };
+
+    if opts.benchmark {
+        print_benchmark(&timings, None, output_bytes as u64);
+    }
+    info!(
+        "Wrote {} rows in {} row groups to {target}",
+        metadata.num_rows,
+        metadata.row_groups.len()
+    );
+    if timings.truncated {
+        warn!(
+            "Stopped at max_output_bytes, the output only contains the first {} rows",
+            metadata.num_rows
+        );
+    }
+
+    if interrupted() {
+        return Err(ParquetError::General(format!(
+            "Interrupted, the output only contains the first {} rows",
+            metadata.num_rows
+        )));
+    }
+    Ok(())
+}
+
+/// Writes the batches to the file at `path`.
+fn write_file(
+    path: &Path,
+    options: ArrowWriterOptions,
+    batches: Batches,
+    opts: &Opts,
+    events: &mut Events,
+) -> Result<(FileMetaData, Timings), ParquetError> {
+    let Batches {
+        reader,
+        pipeline,
+        transform,
+        schema,
+    } = batches;
+    if opts.buffer_in_memory {
+        let mut buffer = Vec::new();
+        ParquetSink::try_new_with_options(&mut buffer, schema, options)
+            .and_then(|sink| write_batches(sink, reader, &pipeline, transform, opts, events))
+            .and_then(|written| {
+                fs::write(path, &buffer)?;
+                Ok(written)
+            })
+    } else {
+        let output = File::create(path)?;
+        let output = match opts.write_buffer_size {
+            Some(capacity) => BufWriter::with_capacity(capacity, output),
+            None => BufWriter::new(output),
+        };
+
+        ParquetSink::try_new_with_options(output, schema, options)
+            .and_then(|sink| write_batches(sink, reader, &pipeline, transform, opts, events))
+    }
+}
+
+/// Post-processes the file written to `write_path` and moves it to the output: rewrites it as
+/// [`rewrite_output`] describes, stores the content hash, then verifies the output and writes the
+/// benchmark, the manifest, and the success marker.
+fn finish_file(
+    write_path: &Path,
+    (metadata, timings): (FileMetaData, Timings),
+    schema: &SchemaRef,
+    inferred_records: Option<usize>,
+    from_reader: bool,
+    opts: &Opts,
+) -> Result<(), ParquetError> {
+    let interrupted = interrupted();
+
+    let metadata = if interrupted {
+        metadata
+    } else {
+        let metadata = rewrite_output(write_path, metadata, schema, opts)?;
+        if opts.content_hash {
+            write_content_hash(write_path, &metadata)?;
+        }
+        metadata
+    };
+
+    if opts.temp_dir.is_some() {
+        debug!("Moving {write_path:?} to {:?}", opts.output);
+        move_file(write_path, &opts.output)?;
+    }
+
+    if opts.benchmark {
+        let input_bytes = fs::metadata(&opts.input)
+            .ok()
+            .filter(|_| !from_reader)
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len());
+        print_benchmark(&timings, input_bytes, fs::metadata(&opts.output)?.len());
+    }
+
+    if opts.verify_after_write {
+        verify_output(&opts.output, metadata.num_rows, opts.first_column_is_key)?;
+        debug!("Verified {:?}", opts.output);
+    }
+
+    info!(
+        "Wrote {} rows in {} row groups to {:?}",
+        metadata.num_rows,
+        metadata.row_groups.len(),
+        opts.output
+    );
+    if timings.truncated {
+        warn!(
+            "Stopped at max_output_bytes, the output only contains the first {} rows",
+            metadata.num_rows
+        );
+    }
+
+    if let Some(ref manifest) = opts.manifest {
+        let file_size = fs::metadata(&opts.output)?.len();
+        let compression = opts
+            .writer_properties_for_schema(schema)
+            .compression(&ColumnPath::new(Vec::new()));
+        let json = serde_json::json!({
+            "files": [opts.output],
+            "schema": schema,
+            "num_rows": metadata.num_rows,
+            "num_row_groups": metadata.row_groups.len(),
+            "file_size": file_size,
+            "compression": compression.to_string(),
+            "inferred_records": inferred_records,
+            "truncated": timings.truncated,
+        });
+
+        let manifest_file = File::create(manifest).map_err(|error| {
+            ParquetError::General(format!(
+                "Error creating manifest file: {manifest:?}, message: {error}"
+            ))
+        })?;
+        serde_json::to_writer_pretty(manifest_file, &json).map_err(|error| {
+            ParquetError::General(format!("Error writing manifest json: {error}"))
+        })?;
+    }
+
+    if interrupted {
+        return Err(ParquetError::General(format!(
+            "Interrupted, the output only contains the first {} rows",
+            metadata.num_rows
+        )));
+    }
+
+    if opts.success_marker {
+        write_success_marker(opts.output.parent().unwrap_or(Path::new("")))?;
+    }
+
+    Ok(())
+}
+
+/// Whether the rows could not be counted ahead for `target_row_groups`, see [`plan_row_groups`].
+fn needs_rechunking(opts: &Opts) -> bool {
+    opts.target_row_groups.is_some() && opts.max_row_group_size.is_none()
+}
+
+/// Rewrites the file at `path` into the row groups of `target_row_groups` if the rows could not be
+/// counted before writing, and without statistics for the columns that are entirely null if
+/// `omit_stats_for_null_columns` is set. Returns the metadata of the file as it is now.
+fn rewrite_output(
+    path: &Path,
+    metadata: FileMetaData,
+    schema: &SchemaRef,
+    opts: &Opts,
+) -> Result<FileMetaData, ParquetError> {
+    let row_group_size = opts
+        .target_row_groups
+        .filter(|_| needs_rechunking(opts))
+        .map(|row_groups| (metadata.num_rows as usize).div_ceil(row_groups).max(1));
+    let null_columns = if opts.omit_stats_for_null_columns {
+        all_null_columns(&metadata)
+    } else {
+        Vec::new()
+    };
+    if row_group_size.is_none() && null_columns.is_empty() {
+        return Ok(metadata);
+    }
+
+    let mut props = opts.writer_properties_builder_for_schema(schema);
+    if let Some(size) = row_group_size {
+        debug!("Rewriting into row groups of up to {size} rows");
+        props = props.set_max_row_group_size(size);
+    }
+    if !null_columns.is_empty() {
+        debug!("Rewriting without statistics for all-null columns {null_columns:?}");
+    }
+    let props = null_columns
+        .iter()
+        .fold(props, |props, column| {
+            props.set_column_statistics_enabled(column.clone(), EnabledStatistics::None)
+        })
+        .build();
+    rewrite_file(path, opts.writer_options(props), row_group_size.is_none())
+}
+
+/// Formats the schema as printed for `print_schema`, on a single line if `compact`.
+fn schema_json(schema: &Schema, compact: bool) -> String {
+    if compact {
+        serde_json::to_string(schema).unwrap()
+    } else {
+        serde_json::to_string_pretty(schema).unwrap()
+    }
+}
+
+/// A CSV column and the output column it is read into, see [`Opts::write_header_map`].
+struct HeaderMapEntry {
+    /// The name of the input file, for the files of `input_dir`.
+    file: Option<String>,
+    /// The header name, none without a header.
+    source: Option<String>,
+    column: String,
+}
+
+/// What the conversions of the files of `input_dir` share or collect to write once for all of them.
+struct DirOutputs {
+    events: Events,
+    header_map: Vec<HeaderMapEntry>,
+}
+
+/// Writes the header map, with the input file of every column if `by_file` is set.
+fn write_header_map(
+    path: &Path,
+    entries: &[HeaderMapEntry],
+    by_file: bool,
+) -> Result<(), ParquetError> {
+    let file = File::create(path).map_err(|error| {
+        ParquetError::General(format!(
+            "Error creating header map file: {path:?}, message: {error}"
+        ))
+    })?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        let mut writer = csv::Writer::from_writer(file);
+        let mut write = || {
+            let header = ["file", "source", "column"];
+            writer.write_record(if by_file { &header[..] } else { &header[1..] })?;
+            for entry in entries {
+                let source = entry.source.as_deref().unwrap_or("");
+                if by_file {
+                    let file = entry.file.as_deref().unwrap_or("");
+                    writer.write_record([file, source, &entry.column])?;
+                } else {
+                    writer.write_record([source, &entry.column])?;
+                }
+            }
+            writer.flush()
+        };
+        write().map_err(|error| {
+            ParquetError::General(format!("Error writing header map csv: {error}"))
+        })
+    } else {
+        let json: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                let mut json =
+                    serde_json::json!({ "source": entry.source, "column": entry.column });
+                if by_file {
+                    json["file"] = serde_json::json!(entry.file);
+                }
+                json
+            })
+            .collect();
+        serde_json::to_writer_pretty(file, &json).map_err(|error| {
+            ParquetError::General(format!("Error writing header map json: {error}"))
+        })
+    }
+}
+
+fn write_success_marker(dir: &Path) -> Result<(), ParquetError> {
+    let marker = dir.join("_SUCCESS");
+    debug!("Writing success marker {marker:?}");
+    File::create(&marker).map_err(|error| {
+        ParquetError::General(format!(
+            "Error creating success marker: {marker:?}, message: {error}"
+        ))
+    })?;
+
+    Ok(())
+}
+
+/// Transcodes UTF-16 input starting with the byte order mark `bom` to UTF-8, dropping the mark.
+fn utf16_decoder<'a>(
+    input: impl Read + 'a,
+    bom: &[u8],
+) -> Result<Box<dyn Read + 'a>, ParquetError> {
+    #[cfg(feature = "encoding")]
+    {
+        let encoding = encoding_rs::Encoding::for_bom(bom).map(|(encoding, _)| encoding);
+        Ok(Box::new(
+            encoding_rs_io::DecodeReaderBytesBuilder::new()
+                .encoding(encoding)
+                .build(input),
+        ))
+    }
+    #[cfg(not(feature = "encoding"))]
+    {
+        let _ = (input, bom);
+        Err(ParquetError::General(
+            "Reading UTF-16 input requires the encoding feature".to_string(),
+        ))
+    }
+}
+
+/// Buffers a non-seekable reader for schema inference, returning the number of bytes inference may
+/// read if `max_infer_bytes` is set.
+fn buffer_input<'a, R: Read + 'a>(
+    reader: R,
+    opts: &Opts,
+) -> (Box<dyn SeekRead + 'a>, Option<usize>) {
+    let reader = SeekableReader::from_unbuffered_reader_with_read_ahead(
+        reader,
+        opts.max_read_records,
+        opts.max_infer_bytes,
+        opts.read_ahead,
+    );
+    // Stop at a line break so inference doesn't see a partial record.
+    let infer_limit = opts
+        .max_infer_bytes
+        .map(|limit| reader.buffered_lines_len(limit));
+
+    (Box::new(reader), infer_limit)
+}
+
+/// Compression of an input file, other than zip archives.
+#[derive(Debug)]
+enum InputCompression {
+    Bzip2,
+    Xz,
+}
+
+impl InputCompression {
+    /// Detects the compression from the file extension or, for regular files, the magic bytes.
+    fn detect(
+        path: &Path,
+        file: &mut File,
+        rewindable: bool,
+    ) -> Result<Option<Self>, ParquetError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("bz2") => return Ok(Some(Self::Bzip2)),
+            Some("xz") => return Ok(Some(Self::Xz)),
+            _ => {}
+        }
+        if !rewindable {
+            return Ok(None);
+        }
+
+        let mut magic = Vec::with_capacity(10);
+        file.take(10).read_to_end(&mut magic)?;
+        file.rewind()?;
+        // A bzip2 stream starts with `BZh`, the block size digit, and the first block's magic, so
+        // text that merely starts with `BZh` is not taken for one.
+        let is_bzip2 = matches!(
+            magic.as_slice(),
+            [
+                b'B',
+                b'Z',
+                b'h',
+                b'1'..=b'9',
+                0x31,
+                0x41,
+                0x59,
+                0x26,
+                0x53,
+                0x59
+            ]
+        );
+        Ok(if is_bzip2 {
+            Some(Self::Bzip2)
+        } else if magic.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else {
+            None
+        })
+    }
+
+    #[cfg_attr(not(any(feature = "bzip2", feature = "xz")), allow(unused_variables))]
+    fn decoder(self, file: File) -> Result<Box<dyn Read>, ParquetError> {
+        match self {
+            #[cfg(feature = "bzip2")]
+            Self::Bzip2 => Ok(Box::new(bzip2::read::MultiBzDecoder::new(file))),
+            #[cfg(feature = "xz")]
+            Self::Xz => Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(file))),
+            #[cfg(not(feature = "bzip2"))]
+            Self::Bzip2 => Err(ParquetError::General(
+                "Reading .bz2 input requires the bzip2 feature".to_string(),
+            )),
+            #[cfg(not(feature = "xz"))]
+            Self::Xz => Err(ParquetError::General(
+                "Reading .xz input requires the xz feature".to_string(),
+            )),
+        }
+    }
+}
+
+/// Picks the archive entry to convert, either the requested one or the only CSV file.
+fn zip_csv_entry(archive: &ZipArchive<File>, entry: Option<&str>) -> Result<String, ParquetError> {
+    if let Some(entry) = entry {
+        return match archive.index_for_name(entry) {
+            Some(_) => Ok(entry.to_string()),
+            None => Err(ParquetError::General(format!(
+                "Zip archive has no entry named {entry:?}"
+            ))),
+        };
+    }
+
+    let csv_entries: Vec<&str> = archive
+        .file_names()
+        .filter(|name| name.to_lowercase().ends_with(".csv"))
+        .collect();
+
+    match csv_entries.as_slice() {
+        [name] => Ok(name.to_string()),
+        [] => Err(ParquetError::General(
+            "Zip archive does not contain a CSV file".to_string(),
+        )),
+        names => Err(ParquetError::General(format!(
+            "Zip archive contains several CSV files, select one with zip_entry: {}",
+            names.join(", ")
+        ))),
+    }
+}
+
+/// Time spent in the read and write phases of a conversion.
+#[derive(Default)]
+struct Timings {
+    read: Duration,
+    write: Duration,
+    rows: usize,
+    /// Whether the conversion stopped early at `max_output_bytes`.
+    truncated: bool,
+}
+
+fn write_batches<W: Write + Send>(
+    mut sink: ParquetSink<W>,
+    mut reader: impl Iterator<Item = Result<RecordBatch, ArrowError>>,
+    pipeline: &BatchPipeline,
+    mut transform: Option<&mut Transform<'_>>,
+    opts: &Opts,
+    events: &mut Events,
+) -> Result<(FileMetaData, Timings), ParquetError> {
+    let mut timings = Timings::default();
+    // The output can only be cut short between row groups, so with `max_output_bytes` they are
+    // ended here rather than by the writer, to check the size before starting each one.
+    let mut group_rows = None;
+    let limit_rows = opts
+        .max_output_bytes
+        .map(|_| match opts.max_row_group_size {
+            _ if opts.single_row_group => usize::MAX,
+            Some(size) => size,
+            None => DEFAULT_MAX_ROW_GROUP_SIZE,
+        });
+    let mut group_start = 0;
+    let mut group_bytes = 0;
+    let mut footer_bytes = sink.empty_size;
+    let mut row_groups = 0;
+
+    while !interrupted() && !timings.truncated {
+        let start = Instant::now();
+        let batch = reader.next();
+        timings.read += start.elapsed();
+
+        let start = Instant::now();
+        match batch {
+            Some(Ok(batch)) => {
+                timings.rows += batch.num_rows();
+                let batch = pipeline.prepare(batch)?;
+                let batch = match transform {
+                    Some(ref mut transform) => transform(batch)?,
+                    None => batch,
+                };
+
+                if let Some(budget) = opts.row_group_memory_budget {
+                    if group_rows.is_none() && batch.num_rows() > 0 {
+                        let row_bytes = (batch.get_array_memory_size() / batch.num_rows()).max(1);
+                        let rows = (budget / row_bytes).max(1);
+                        debug!("Estimated {row_bytes} bytes per row, writing row groups of up to {rows} rows");
+                        group_rows = Some(rows);
+                    }
+                }
+
+                match group_rows.or(limit_rows) {
+                    Some(rows) => {
+                        let mut offset = 0;
+                        while offset < batch.num_rows() {
+                            // The next row group is guessed to be as large as the previous one.
+                            if let Some(limit) = opts.max_output_bytes {
+                                if sink.in_progress_rows() == 0
+                                    && sink.estimated_size() + footer_bytes + group_bytes > limit
+                                {
+                                    timings.truncated = true;
+                                    break;
+                                }
+                            }
+
+                            let len =
+                                (rows - sink.in_progress_rows()).min(batch.num_rows() - offset);
+                            sink.write_batch(&batch.slice(offset, len))?;
+                            offset += len;
+                            if sink.in_progress_rows() >= rows {
+                                sink.flush_row_group()?;
+                            }
+                            // The writer also ends a row group once it has `rows` rows itself.
+                            if sink.in_progress_rows() == 0 {
+                                let footer = sink.last_row_group_footer_size()?;
+                                footer_bytes += footer;
+                                group_bytes = sink.estimated_size() - group_start + footer;
+                                group_start = sink.estimated_size();
+                            }
+                        }
+                    }
+                    None => sink.write_batch(&batch)?,
+                }
+
+                events.emit(serde_json::json!({ "event": "batch", "rows": batch.num_rows() }))?;
+                for _ in row_groups..sink.flushed_row_groups() {
+                    events.emit(serde_json::json!({ "event": "row_group_flush" }))?;
+                }
+                row_groups = sink.flushed_row_groups();
+            }
+            Some(Err(error)) => return Err(error.into()),
+            None => break,
+        }
+        timings.write += start.elapsed();
+    }
+
+    let start = Instant::now();
+    let metadata = sink.finish()?;
+    timings.write += start.elapsed();
+
+    for _ in row_groups..metadata.row_groups.len() {
+        events.emit(serde_json::json!({ "event": "row_group_flush" }))?;
+    }
+    events.emit(serde_json::json!({ "event": "done", "rows": metadata.num_rows }))?;
+
+    Ok((metadata, timings))
+}
+
+/// Writes progress events, one JSON object per line, to the `events` file if set.
+struct Events {
+    file: Option<File>,
+}
+
+impl Events {
+    fn create(path: Option<&Path>) -> Result<Self, ParquetError> {
+        let file = path
+            .map(|path| {
+                File::create(path).map_err(|error| {
+                    ParquetError::General(format!(
+                        "Error creating events file: {path:?}, message: {error}"
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(Self { file })
+    }
+
+    fn emit(&mut self, event: serde_json::Value) -> Result<(), ParquetError> {
+        if let Some(ref mut file) = self.file {
+            // Each event is written at once so readers never see a partial line.
+            file.write_all(format!("{event}\n").as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn print_benchmark(timings: &Timings, input_bytes: Option<u64>, output_bytes: u64) {
+    let phase = |name: &str, duration: Duration, bytes: Option<u64>| {
+        let seconds = duration.as_secs_f64().max(f64::EPSILON);
+        let mut line = format!(
+            "{name}: {seconds:.3}s, {:.0} rows/s",
+            timings.rows as f64 / seconds
+        );
+        if let Some(bytes) = bytes {
+            line.push_str(&format!(", {:.2} MB/s", bytes as f64 / 1e6 / seconds));
+        }
+        info!("{line}");
+    };
+
+    info!("Benchmark ({} rows):", timings.rows);
+    phase("read", timings.read, input_bytes);
+    phase("write", timings.write, Some(output_bytes));
+}
+
+/// Turns batches as read from the CSV into ones matching the output schema.
+struct BatchPipeline {
+    schema: SchemaRef,
+    projection: Option<Vec<usize>>,
+    null_values: Vec<(usize, ArrayRef)>,
+    null_fills: Vec<(usize, ArrayRef)>,
+    /// The schema before columns with filled nulls were marked non-null.
+    unfilled_schema: SchemaRef,
+    keep_empty_strings: bool,
+    unescape_newlines: bool,
+    nan_as_null: bool,
+    decimal_rounding: Option<RoundingMode>,
+    number_formats: Vec<(usize, NumberFormat)>,
+    sort_check: Option<RefCell<SortCheck>>,
+}
+
+impl BatchPipeline {
+    fn try_new(
+        schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+        opts: &Opts,
+    ) -> Result<Self, ParquetError> {
+        let null_values = opts
+            .column_null_values
+            .iter()
+            .map(|(column, value)| {
+                let index = schema.index_of(column).map_err(|_| {
+                    ParquetError::General(format!("Unknown column {column:?} for null values"))
+                })?;
+                let null_value = cast(
+                    &StringArray::from(vec![value.as_str()]),
+                    schema.field(index).data_type(),
+                )?;
+                if null_value.is_null(0) {
+                    return Err(ParquetError::General(format!(
+                        "Null value {value:?} is not a valid {} for column {column:?}",
+                        schema.field(index).data_type()
+                    )));
+                }
+                Ok((index, null_value))
+            })
+            .collect::<Result<Vec<_>, ParquetError>>()?;
+
+        let number_formats = opts
+            .column_number_format
+            .iter()
+            .map(|(column, format)| {
+                let index = schema.index_of(column).map_err(|_| {
+                    ParquetError::General(format!("Unknown column {column:?} for number formats"))
+                })?;
+                let data_type = schema.field(index).data_type();
+                if !data_type.is_numeric() {
+                    return Err(ParquetError::General(format!(
+                        "Cannot set the number format of column {column:?} of type {data_type}"
+                    )));
+                }
+                Ok((index, *format))
+            })
+            .collect::<Result<Vec<_>, ParquetError>>()?;
+
+        let mut null_fills = opts
+            .column_defaults
+            .iter()
+            .map(|(column, value)| {
+                let index = schema.index_of(column).map_err(|_| {
+                    ParquetError::General(format!("Unknown column {column:?} for defaults"))
+                })?;
+                let default = cast(
+                    &StringArray::from(vec![value.as_str()]),
+                    schema.field(index).data_type(),
+                )?;
+                if default.is_null(0) {
+                    return Err(ParquetError::General(format!(
+                        "Default {value:?} is not a valid {} for column {column:?}",
+                        schema.field(index).data_type()
+                    )));
+                }
+                Ok((index, default))
+            })
+            .collect::<Result<Vec<_>, ParquetError>>()?;
+        if let Some(ref sentinel) = opts.null_sentinel {
+            for (index, field) in schema.fields().iter().enumerate() {
+                if null_fills.iter().any(|(fill, _)| *fill == index) {
+                    continue;
+                }
+                let value = cast(
+                    &StringArray::from(vec![sentinel.as_str()]),
+                    field.data_type(),
+                );
+                if let Some(value) = value.ok().filter(|value| !value.is_null(0)) {
+                    null_fills.push((index, value));
+                }
+            }
+        }
+        let unfilled_schema = schema.clone();
+        let schema = Arc::new(Schema::new_with_metadata(
+            schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    if null_fills.iter().any(|(fill, _)| *fill == index) {
+                        field.as_ref().clone().with_nullable(false)
+                    } else {
+                        field.as_ref().clone()
+                    }
+                })
+                .collect::<Vec<_>>(),
+            schema.metadata().clone(),
+        ));
+
+        Ok(Self {
+            schema,
+            projection,
+            null_values,
+            null_fills,
+            unfilled_schema,
+            keep_empty_strings: !opts.quoted_empty_as_null || opts.empty_as_null == Some(false),
+            unescape_newlines: opts.unescape_newlines,
+            nan_as_null: opts.nan_as_null,
+            decimal_rounding: opts.decimal_rounding,
+            number_formats,
+            sort_check: opts.verify_sorted.then(|| RefCell::new(SortCheck::new())),
+        })
+    }
+
+    fn prepare(&self, batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+        let batch = match self.projection {
+            Some(ref indices) => batch.project(indices)?,
+            None => batch,
+        };
+        let batch = if self.keep_empty_strings {
+            batch
+        } else {
+            replace_empty_strings_with_nulls(batch).unwrap()
+        };
+
+        for (index, (field, column)) in self.schema.fields().iter().zip(batch.columns()).enumerate()
+        {
+            let filled = self.null_fills.iter().any(|(fill, _)| *fill == index);
+            if !field.is_nullable() && !filled && column.null_count() > 0 {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Column {:?} is declared non-null but contains {} null values",
+                    field.name(),
+                    column.null_count()
+                )));
+            }
+        }
+
+        let batch = conform_to_schema(
+            batch,
+            &self.unfilled_schema,
+            self.decimal_rounding,
+            &self.number_formats,
+        )?;
+        let batch = if self.unescape_newlines {
+            unescape_newlines(batch)?
+        } else {
+            batch
+        };
+        let batch = if self.nan_as_null {
+            replace_nans_with_nulls(batch)?
+        } else {
+            batch
+        };
+
+        let batch = if self.null_values.is_empty() {
+            batch
+        } else {
+            let mut columns = batch.columns().to_vec();
+            for (index, value) in &self.null_values {
+                let matches = eq(&columns[*index], &Scalar::new(value))?;
+                columns[*index] = nullif(&columns[*index], &matches)?;
+            }
+            RecordBatch::try_new(batch.schema(), columns)?
+        };
+
+        let batch = if self.null_fills.is_empty() {
+            batch
+        } else {
+            let mut columns = batch.columns().to_vec();
+            for (index, value) in &self.null_fills {
+                let nulls = is_null(&columns[*index])?;
+                columns[*index] = zip(&nulls, &Scalar::new(value), &columns[*index])?;
+            }
+            RecordBatch::try_new(self.schema.clone(), columns)?
+        };
+
+        if let Some(ref sort_check) = self.sort_check {
+            if let Some(row) = sort_check.borrow_mut().check(batch.column(0))? {
+                return Err(ArrowError::InvalidArgumentError(format!(
+                    "Column {:?} is declared sorted but is out of order at row {row}",
+                    self.schema.field(0).name()
+                )));
+            }
+        }
+
+        Ok(batch)
+    }
+}
+
+/// Checks that a column stays sorted ascending with nulls last over consecutive batches.
+struct SortCheck {
+    previous: Option<ArrayRef>,
+    rows: usize,
+}
+
+impl SortCheck {
+    const SORT_OPTIONS: SortOptions = SortOptions {
+        descending: false,
+        nulls_first: false,
+    };
+
+    fn new() -> Self {
+        Self {
+            previous: None,
+            rows: 0,
+        }
+    }
+
+    /// Returns the first row, counted over all batches so far, that sorts before the row preceding it.
+    fn check(&mut self, column: &ArrayRef) -> Result<Option<usize>, ArrowError> {
+        if column.is_empty() {
+            return Ok(None);
+        }
+
+        let offset = self.rows;
+        self.rows += column.len();
+
+        if let Some(ref previous) = self.previous {
+            let compare = make_comparator(previous, column, Self::SORT_OPTIONS)?;
+            if compare(0, 0).is_gt() {
+                return Ok(Some(offset));
+            }
+        }
+
+        let compare = make_comparator(column, column, Self::SORT_OPTIONS)?;
+        if let Some(row) = (1..column.len()).find(|&row| compare(row - 1, row).is_gt()) {
+            return Ok(Some(offset + row));
+        }
+
+        self.previous = Some(column.slice(column.len() - 1, 1));
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "interrupt")]
+use interrupt::interrupted;
+
+#[cfg(not(feature = "interrupt"))]
+fn interrupted() -> bool {
+    false
+}
+
+#[cfg(feature = "interrupt")]
+mod interrupt {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+    /// Whether SIGINT was received while an [`InterruptGuard`] was installed.
+    pub fn interrupted() -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+
+    extern "C" fn handle_sigint(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // A second SIGINT terminates immediately.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    /// Records SIGINT instead of terminating until dropped, then restores the previous handler.
+    /// The flag is cleared on both ends, so an interrupt only affects the conversion it happened
+    /// in.
+    pub struct InterruptGuard {
+        previous: libc::sighandler_t,
+    }
+
+    impl InterruptGuard {
+        pub fn install() -> Self {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+            let handler = handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+            Self { previous }
+        }
+    }
+
+    impl Drop for InterruptGuard {
+        fn drop(&mut self) {
+            unsafe {
+                libc::signal(libc::SIGINT, self.previous);
+            }
+            INTERRUPTED.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(feature = "object_store")]
+mod object_store_output {
+    use object_store::{path::Path, ObjectStore, WriteMultipart};
+    use parquet::errors::ParquetError;
+    use std::io::{self, Write};
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    /// The number of parts uploaded at the same time before writing waits for one to finish.
+    const MAX_CONCURRENT_PARTS: usize = 8;
+
+    fn upload_error(error: object_store::Error) -> ParquetError {
+        ParquetError::General(format!("Error uploading to object store: {error}"))
+    }
+
+    /// Uploads the bytes written to it as a multipart upload, sending parts in the background
+    /// while writing continues.
+    pub struct ObjectStoreWriter {
+        runtime: Runtime,
+        upload: Option<WriteMultipart>,
+        location: Path,
+        bytes: usize,
+    }
+
+    impl ObjectStoreWriter {
+        pub fn try_new(store: Arc<dyn ObjectStore>, location: Path) -> Result<Self, ParquetError> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let upload = runtime
+                .block_on(store.put_multipart(&location))
+                .map_err(upload_error)?;
+            Ok(Self {
+                runtime,
+                upload: Some(WriteMultipart::new(upload)),
+                location,
+                bytes: 0,
+            })
+        }
+
+        pub fn location(&self) -> &Path {
+            &self.location
+        }
+
+        pub fn bytes_written(&self) -> usize {
+            self.bytes
+        }
+
+        /// Uploads the remaining bytes and completes the upload.
+        pub fn finish(&mut self) -> Result<(), ParquetError> {
+            if let Some(upload) = self.upload.take() {
+                self.runtime
+                    .block_on(upload.finish())
+                    .map_err(upload_error)?;
+            }
+            Ok(())
+        }
+
+        /// Cancels the upload if it wasn't finished, discarding the parts uploaded so far.
+        pub fn abort(&mut self) -> Result<(), ParquetError> {
+            if let Some(upload) = self.upload.take() {
+                self.runtime
+                    .block_on(upload.abort())
+                    .map_err(upload_error)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Write for ObjectStoreWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let upload = self
+                .upload
+                .as_mut()
+                .ok_or_else(|| io::Error::other("The upload is already finished"))?;
+            self.runtime
+                .block_on(upload.wait_for_capacity(MAX_CONCURRENT_PARTS))
+                .map_err(io::Error::other)?;
+
+            // Full parts are uploaded by tasks spawned on the runtime.
+            let _guard = self.runtime.enter();
+            upload.write(buf);
+            self.bytes += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+/// What one pass over the input found for the options that need more than arrow's schema
+/// inference, see [`pre_scan`].
+#[derive(Default)]
+struct PreScan {
+    /// The names in the header record, if it was needed.
+    header: Option<Vec<String>>,
+    /// The number of data records, if they were counted.
+    records: Option<usize>,
+    /// For every column, whether a sampled value was seen and the [`InferType`]s all of them fit.
+    fits: Vec<(bool, [bool; 5])>,
+    /// For every column, what the sampled values have in common as integers.
+    integers: Vec<IntegerCandidate>,
+    /// For every column, the total length and number of the non-empty sampled values.
+    lengths: Vec<(usize, usize)>,
+}
+
+impl PreScan {
+    /// The header names, none if the input is empty.
+    fn header(&self) -> &[String] {
+        self.header.as_deref().unwrap_or_default()
+    }
+
+    /// The average length in bytes of the non-empty sampled values of each column.
+    fn average_lengths(&self) -> impl Iterator<Item = f64> + '_ {
+        self.lengths
+            .iter()
+            .map(|(length, count)| *length as f64 / (*count).max(1) as f64)
+    }
+
+    /// Adds the values of a sampled record to the type statistics of the `inferred` columns, and
+    /// to the value lengths if `lengths` is set.
+    fn sample(&mut self, record: &csv::ByteRecord, inferred: Option<&[bool]>, lengths: bool) {
+        if inferred.is_some() && self.fits.len() < record.len() {
+            self.fits.resize(record.len(), (false, [true; 5]));
+            self.integers.resize_with(record.len(), Default::default);
+        }
+        if lengths && self.lengths.len() < record.len() {
+            self.lengths.resize(record.len(), (0, 0));
+        }
+
+        for (column, value) in record.iter().enumerate() {
+            if lengths && !value.is_empty() {
+                self.lengths[column].0 += value.len();
+                self.lengths[column].1 += 1;
+            }
+
+            let Some(inferred) = inferred else {
+                continue;
+            };
+            if !inferred.get(column).copied().unwrap_or_default() {
+                continue;
+            }
+            // Values that are not UTF-8 cannot be anything but strings.
+            let value = String::from_utf8_lossy(value);
+            self.integers[column].update(&value);
+
+            if value.is_empty() {
+                continue;
+            }
+            let (seen, fits) = &mut self.fits[column];
+            *seen = true;
+            let matches = INFER_PATTERNS.matches(&value);
+            let integer = matches.matched(1) && value.parse::<i64>().is_ok();
+            fits[0] &= matches.matched(0);
+            fits[1] &= integer;
+            fits[2] &= integer || matches.matched(2);
+            fits[3] &= matches.matched(3);
+            fits[4] &= matches.matched(3) || matches.matched(4);
+        }
+    }
+}
+
+/// Reads the input once for everything the options need from it besides arrow's inference: the
+/// header, the number of records for `target_row_groups` if the input can be rewound from its
+/// end, and the values of the records sampled
+/// for inference, for the type options if the schema was `inferred` and for
+/// `no_dictionary_for_large_columns`. The input is rewound afterwards.
+fn pre_scan(
+    input: &mut Input,
+    opts: &Opts,
+    inferred: Option<&Schema>,
+) -> Result<PreScan, ParquetError> {
+    let has_header = opts.header.unwrap_or(true);
+    let inferred: Option<Vec<bool>> = inferred
+        .filter(|_| {
+            opts.max_read_records != Some(0)
+                && (opts.type_priority.is_some()
+                    || opts.lenient_integers
+                    || opts.large_int_as_decimal)
+        })
+        .map(|schema| {
+            schema
+                .fields()
+                .iter()
+                .map(|field| opts.infers_column(field.name()))
+                .collect()
+        });
+    let sample_lengths = opts.no_dictionary_for_large_columns.is_some();
+    let read_header = has_header
+        && (opts.match_schema_to_header
+            || opts.reorder_to_schema
+            || opts.write_header_map.is_some());
+    let count = opts.target_row_groups.is_some() && input.rewindable;
+
+    let mut scan = PreScan::default();
+    let mut sampling = inferred.is_some() || sample_lengths;
+    if !(sampling || read_header || count) {
+        return Ok(scan);
+    }
+
+    // Counting needs a rewindable input, anything else stays within what inference may read so
+    // that buffered input can still be rewound.
+    let source: Box<dyn Read + '_> = if count {
+        Box::new(&mut input.reader)
+    } else {
+        input.sample()
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(opts.delimiter as u8)
+        .escape(Some(opts.escape_byte()))
+        .quote(b'"')
+        .from_reader(source);
+
+    let mut record = csv::ByteRecord::new();
+    let mut records = 0;
+    let mut header_pending = has_header;
+    // Past the sample, records are only read further to count them.
+    while count || sampling || header_pending {
+        let more = reader
+            .read_byte_record(&mut record)
+            .map_err(|error| ParquetError::General(format!("Error scanning the input: {error}")))?;
+        if !more {
+            break;
+        }
+
+        if std::mem::take(&mut header_pending) {
+            if read_header {
+                scan.header = Some(
+                    record
+                        .iter()
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .collect(),
+                );
+            }
+            continue;
+        }
+
+        records += 1;
+        sampling &= opts.max_read_records.is_none_or(|max| records <= max);
+        if sampling {
+            scan.sample(&record, inferred.as_deref(), sample_lengths);
+        }
+    }
+
+    scan.records = count.then_some(records);
+    drop(reader);
+    input.reader.rewind()?;
+    Ok(scan)
+}
+
+/// Counts the data records in the input, not including the header.
+fn count_records(input: &mut dyn Read, opts: &Opts) -> Result<usize, ParquetError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(opts.header.unwrap_or(true))
+        .flexible(true)
+        .delimiter(opts.delimiter as u8)
+        .escape(Some(opts.escape_byte()))
+        .quote(b'"')
+        .from_reader(input);
+
+    let mut record = csv::ByteRecord::new();
+    let mut rows = 0;
+    while reader
+        .read_byte_record(&mut record)
+        .map_err(|error| ParquetError::General(format!("Error counting rows: {error}")))?
+    {
+        rows += 1;
+    }
+
+    Ok(rows)
+}
+
+/// The number of lines and bytes looked at to detect the delimiter.
+const SNIFF_LINES: usize = 10;
+const SNIFF_BYTES: usize = 64 * 1024;
+
+const DELIMITER_CANDIDATES: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Reads up to `lines` complete lines and at most `bytes` bytes from the start of the input.
+fn read_sample(input: &mut dyn Read, lines: usize, bytes: usize) -> Result<Vec<u8>, ParquetError> {
+    // Read byte by byte so we never read past the buffered part of piped input.
+    let mut sample = Vec::new();
+    let mut complete = 0;
+    let mut newlines = 0;
+    let mut byte = [0u8];
+    while sample.len() < bytes && input.read(&mut byte)? == 1 {
+        let byte = byte[0];
+        sample.push(byte);
+        if byte == b'\n' {
+            complete = sample.len();
+            newlines += 1;
+            if newlines >= lines {
+                break;
+            }
+        }
+    }
+    // Drop a trailing partial line unless it is all we have.
+    if complete > 0 && sample.len() == bytes {
+        sample.truncate(complete);
+    }
+
+    Ok(sample)
+}
+
+/// Returns the candidate delimiter that splits the sampled lines into the same number of fields,
+/// preferring the one giving the most fields.
+fn detect_delimiter(sample: &[u8], escape: u8) -> Option<u8> {
+    let mut best: Option<(u8, usize)> = None;
+    for delimiter in DELIMITER_CANDIDATES {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .escape(Some(escape))
+            .quote(b'"')
+            .from_reader(sample);
+
+        let mut fields = None;
+        let mut consistent = true;
+        for record in reader.byte_records() {
+            let Ok(record) = record else {
+                consistent = false;
+                break;
+            };
+            if *fields.get_or_insert(record.len()) != record.len() {
+                consistent = false;
+                break;
+            }
+        }
+
+        match fields {
+            Some(fields)
+                if consistent
+                    && fields > 1
+                    && best.is_none_or(|(_, best_fields)| fields > best_fields) =>
+            {
+                best = Some((delimiter, fields))
+            }
+            _ => {}
+        }
+    }
+
+    best.map(|(delimiter, _)| delimiter)
+}
+
+/// Guesses whether the first sampled line is a header by comparing each of its fields with the
+/// rest of the column: a column of numbers or of values with the same length votes for a header if
+/// the first field differs and against it otherwise. Returns `None` without votes.
+fn detect_header(sample: &[u8], delimiter: u8, escape: u8) -> Option<bool> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .escape(Some(escape))
+        .quote(b'"')
+        .from_reader(sample);
+    let records = reader.records().collect::<Result<Vec<_>, _>>().ok()?;
+    let (first, rest) = records.split_first()?;
+    if rest.is_empty() {
+        return None;
+    }
+
+    let is_number = |value: &str| value.trim().parse::<f64>().is_ok();
+    let mut votes = 0i32;
+    for (column, header) in first.iter().enumerate() {
+        let values: Vec<&str> = rest
+            .iter()
+            .filter_map(|record| record.get(column))
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+
+        if values.iter().all(|value| is_number(value)) {
+            votes += if is_number(header) { -1 } else { 1 };
+        } else if values.iter().all(|value| value.len() == values[0].len()) {
+            votes += if header.len() == values[0].len() {
+                -1
+            } else {
+                1
+            };
+        }
+    }
+
+    (votes != 0).then_some(votes > 0)
+}
+
+/// The number of rows encoded in memory to estimate the output size.
+const ESTIMATE_SAMPLE_ROWS: usize = 100_000;
+
+/// Encodes the first rows with the given properties and scales the size up to all rows.
+fn estimate_output_size(
+    reader: impl Iterator<Item = Result<RecordBatch, ArrowError>>,
+    pipeline: &BatchPipeline,
+    props: WriterProperties,
+) -> Result<u64, ParquetError> {
+    let mut sink = ParquetSink::try_new(Vec::new(), pipeline.schema.clone(), props)?;
+    let mut sampled_rows = 0;
+    let mut total_rows = 0;
+
+    for batch in reader {
+        let batch = batch?;
+        total_rows += batch.num_rows();
+        if sampled_rows < ESTIMATE_SAMPLE_ROWS {
+            let batch = pipeline.prepare(batch)?;
+            let batch = batch.slice(0, batch.num_rows().min(ESTIMATE_SAMPLE_ROWS - sampled_rows));
+            sampled_rows += batch.num_rows();
+            sink.write_batch(&batch)?;
+        }
+    }
+
+    let sample_size = sink.into_inner()?.len() as u64;
+    if sampled_rows == total_rows {
+        return Ok(sample_size);
+    }
+
+    Ok((sample_size as f64 * total_rows as f64 / sampled_rows as f64).round() as u64)
+}
+
+/// Checks that every column named in an option is in the schema.
+fn validate_columns(schema: &Schema, columns: &[String], option: &str) -> Result<(), ParquetError> {
+    match columns
+        .iter()
+        .find(|column| schema.index_of(column).is_err())
+    {
+        Some(column) => Err(ParquetError::General(format!(
+            "Unknown column {column:?} in {option}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Fails on duplicate column names, or renames them with a numeric suffix if `dedup` is set.
+fn dedup_field_names(schema: Schema, dedup: bool) -> Result<Schema, ParquetError> {
+    let mut seen = HashSet::new();
+    let mut fields = Vec::with_capacity(schema.fields().len());
+
+    for field in schema.fields() {
+        let mut name = field.name().clone();
+        if seen.contains(&name) {
+            if !dedup {
+                return Err(ParquetError::General(format!(
+                    "Duplicate column name {name:?} in the CSV header"
+                )));
+            }
+            let mut suffix = 1;
+            while seen.contains(&format!("{}_{suffix}", field.name())) {
+                suffix += 1;
+            }
+            name = format!("{}_{suffix}", field.name());
+            debug!("Renaming duplicate column {:?} to {name:?}", field.name());
+        }
+        seen.insert(name.clone());
+        fields.push(field.as_ref().clone().with_name(name));
+    }
+
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Infers the schema of `sample` with arrow. With `infer_only_columns`, only those columns are
+/// copied into a sample that arrow infers the types of and all other columns are strings, so the
+/// per-value work of inference is skipped for them.
+fn infer_schema(
+    format: &Format,
+    sample: impl Read,
+    opts: &Opts,
+) -> Result<(Schema, usize), ArrowError> {
+    if opts.infer_only_columns.is_empty() {
+        return format.infer_schema(sample, opts.max_read_records);
+    }
+
+    let csv_error = |error: csv::Error| ArrowError::CsvError(error.to_string());
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(opts.delimiter as u8)
+        .escape(Some(opts.escape_byte()))
+        .quote(b'"')
+        .from_reader(sample);
+
+    let mut record = csv::ByteRecord::new();
+    let has_record = reader.read_byte_record(&mut record).map_err(csv_error)?;
+    let has_header = opts.header.unwrap_or(true);
+    let names: Vec<String> = if has_header {
+        record
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
+    } else {
+        (1..=record.len()).map(|i| format!("column_{i}")).collect()
+    };
+    let columns: Vec<usize> = (0..names.len())
+        .filter(|&column| opts.infers_column(&names[column]))
+        .collect();
+
+    let mut projected = csv::Writer::from_writer(Vec::new());
+    projected
+        .write_record(columns.iter().map(|&column| &names[column]))
+        .map_err(csv_error)?;
+    let mut records = 0;
+    let mut pending = has_record && !has_header;
+    while opts.max_read_records.is_none_or(|max| records < max) {
+        if !pending && !reader.read_byte_record(&mut record).map_err(csv_error)? {
+            break;
+        }
+        pending = false;
+        records += 1;
+        if !columns.is_empty() {
+            projected
+                .write_record(
+                    columns
+                        .iter()
+                        .map(|&column| record.get(column).unwrap_or_default()),
+                )
+                .map_err(csv_error)?;
+        }
+    }
+
+    let types = if columns.is_empty() {
+        Vec::new()
+    } else {
+        let projected = projected
+            .into_inner()
+            .map_err(|error| ArrowError::CsvError(error.to_string()))?;
+        let (schema, _) = Format::default()
+            .with_header(true)
+            .infer_schema(projected.as_slice(), None)?;
+        schema
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect()
+    };
+
+    let fields = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let data_type = columns
+                .iter()
+                .position(|&column| column == index)
+                .map_or(DataType::Utf8, |position| types[position].clone());
+            Field::new(name, data_type, true)
+        })
+        .collect::<Vec<_>>();
+    Ok((Schema::new(fields), records))
+}
+
+/// Matches the schema fields to the CSV header by name, returning the schema in header order and
+/// the indices that project a batch read with it back to the order of `schema`.
+fn reorder_schema(
+    schema: &Schema,
+    header: &[String],
+) -> Result<(Schema, Vec<usize>), ParquetError> {
+    let fields = header
+        .iter()
+        .map(|column| match schema.field_with_name(column) {
+            Ok(field) => Ok(field.clone()),
+            Err(_) => Err(ParquetError::General(format!(
+                "CSV column {column:?} is not in the schema"
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let projection = schema
+        .fields()
+        .iter()
+        .map(
+            |field| match header.iter().position(|column| column == field.name()) {
+                Some(index) => Ok(index),
+                None => Err(ParquetError::General(format!(
+                    "Schema field {:?} is not in the CSV header",
+                    field.name()
+                ))),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        Schema::new_with_metadata(fields, schema.metadata().clone()),
+        projection,
+    ))
+}
+
+/// Reads every row group of a written file and checks the number of rows, and that the first
+/// column is sorted if `sorted` is set.
+fn verify_output(path: &Path, expected_rows: i64, sorted: bool) -> Result<(), ParquetError> {
+    let file = File::open(path)?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+
+    let mut rows = 0;
+    let mut sort_check = SortCheck::new();
+    for batch in reader {
+        let batch = batch?;
+        rows += batch.num_rows() as i64;
+
+        if sorted {
+            if let Some(row) = sort_check.check(batch.column(0))? {
+                return Err(ParquetError::General(format!(
+                    "Verification of {path:?} failed, the first column is not sorted at row {row}"
+                )));
+            }
+        }
+    }
+
+    if rows != expected_rows {
+        return Err(ParquetError::General(format!(
+            "Verification of {path:?} failed, wrote {expected_rows} rows but read back {rows}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Hashes the bytes of the written file before its footer metadata and replaces the footer with
+/// `metadata` plus the hash as `content_hash` key-value metadata. The hashed bytes are kept as
+/// they are, so the hash can be recomputed from the final file.
+fn write_content_hash(path: &Path, metadata: &FileMetaData) -> Result<(), ParquetError> {
+    let bytes = fs::read(path)?;
+    let footer_start = bytes.len().checked_sub(FOOTER_SIZE).ok_or_else(|| {
+        ParquetError::General(format!("{path:?} is too short to be a parquet file"))
+    })?;
+    let footer: &[u8; FOOTER_SIZE] = bytes[footer_start..].try_into().unwrap();
+    let data_len = footer_start
+        .checked_sub(ParquetMetaDataReader::decode_footer(footer)?)
+        .ok_or_else(|| ParquetError::General(format!("{path:?} has an invalid footer length")))?;
+
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(&bytes[..data_len]);
+    let hash = format!("xxh64:{:016x}", hasher.finish());
+    debug!("Content hash of {path:?} is {hash}");
+
+    let mut metadata = metadata.clone();
+    metadata
+        .key_value_metadata
+        .get_or_insert_with(Vec::new)
+        .push(KeyValue::new("content_hash".to_string(), hash));
+    let mut encoded = Vec::new();
+    metadata.write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut encoded))?;
+
+    let mut output = fs::OpenOptions::new().write(true).open(path)?;
+    output.set_len(data_len as u64)?;
+    output.seek(SeekFrom::End(0))?;
+    output.write_all(&encoded)?;
+    output.write_all(&(encoded.len() as u32).to_le_bytes())?;
+    output.write_all(&footer[4..])?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Returns the columns whose chunks in every row group only hold nulls, according to their
+/// statistics. Without row groups or statistics no column is returned.
+fn all_null_columns(metadata: &FileMetaData) -> Vec<ColumnPath> {
+    let Some(first) = metadata.row_groups.first() else {
+        return Vec::new();
+    };
+
+    (0..first.columns.len())
+        .filter(|&column| {
+            metadata.row_groups.iter().all(|row_group| {
+                row_group.columns[column]
+                    .meta_data
+                    .as_ref()
+                    .and_then(|meta_data| meta_data.statistics.as_ref())
+                    .and_then(|statistics| statistics.null_count)
+                    .is_some_and(|nulls| nulls == row_group.num_rows)
+            })
+        })
+        .filter_map(|column| first.columns[column].meta_data.as_ref())
+        .map(|meta_data| ColumnPath::new(meta_data.path_in_schema.clone()))
+        .collect()
+}
+
+/// Reads the written file back and rewrites it with `options`, row group by row group if
+/// `keep_row_groups` is set, returning the metadata of the rewritten file.
+fn rewrite_file(
+    path: &Path,
+    options: ArrowWriterOptions,
+    keep_row_groups: bool,
+) -> Result<FileMetaData, ParquetError> {
+    let input = File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(input.try_clone()?)?;
+    let schema = builder.schema().clone();
+    let num_row_groups = builder.metadata().num_row_groups();
+
+    let rewrite_path = path.with_extension("rewrite.tmp");
+    let output = BufWriter::new(File::create(&rewrite_path)?);
+    let mut writer = ArrowWriter::try_new_with_options(output, schema, options)?;
+
+    let rewritten = (0..num_row_groups)
+        .try_for_each(|row_group| {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(input.try_clone()?)?
+                .with_row_groups(vec![row_group])
+                .build()?;
+            for batch in reader {
+                writer.write(&batch?)?;
+            }
+            if keep_row_groups {
+                writer.flush()
+            } else {
+                Ok(())
+            }
+        })
+        .and_then(|_| writer.close());
+
+    match rewritten {
+        Ok(metadata) => move_file(&rewrite_path, path).map(|_| metadata),
+        Err(error) => {
+            let _ = fs::remove_file(&rewrite_path);
+            Err(error)
+        }
+    }
+}
+
+/// Renames `from` to `to`, falling back to copying when they are on different filesystems.
+fn move_file(from: &Path, to: &Path) -> Result<(), ParquetError> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    let copied = fs::copy(from, to);
+    let _ = fs::remove_file(from);
+    match copied {
+        Ok(_) => Ok(()),
+        Err(error) => Err(ParquetError::General(format!(
+            "Error moving {from:?} to {to:?}, message: {error}"
+        ))),
+    }
+}
+
+/// Marks string columns as `Int64` if lenient and every sampled value is an integer once
+/// surrounding spaces are removed, allowing a leading `+` and leading zeros. Otherwise marks them
+/// as `Decimal128(38, 0)` if large integers are read as decimals and every sampled value is an
+/// integer of up to 38 digits, some too large for `Int64`.
+fn integer_columns(schema: &Schema, scan: &PreScan, opts: &Opts) -> Schema {
+    let candidates = schema.fields().iter().enumerate().map(|(index, field)| {
+        scan.integers
+            .get(index)
+            .filter(|_| field.data_type() == &DataType::Utf8 && opts.infers_column(field.name()))
+    });
+
+    let fields = schema
+        .fields()
+        .iter()
+        .zip(candidates)
+        .map(|(field, candidate)| match candidate {
+            Some(candidate) if opts.lenient_integers && candidate.is_lenient_integer() => {
+                field.as_ref().clone().with_data_type(DataType::Int64)
+            }
+            Some(candidate) if opts.large_int_as_decimal && candidate.is_large_integer() => field
+                .as_ref()
+                .clone()
+                .with_data_type(DataType::Decimal128(38, 0)),
+            _ => field.as_ref().clone(),
+        })
+        .collect::<Vec<_>>();
+
+    Schema::new_with_metadata(fields, schema.metadata().clone())
+}
+
+/// Picks the arrow type for `field` that the writer stores with `physical_type`, keeping the type
+/// of the field if it already is.
+fn data_type_for_physical(
+    field: &Field,
+    physical_type: PhysicalType,
+) -> Result<DataType, ParquetError> {
+    let descriptor = arrow_to_parquet_schema(&Schema::new(vec![field.clone()]))?;
+    if descriptor.column(0).physical_type() == physical_type {
+        return Ok(field.data_type().clone());
+    }
+
+    let data_type = field.data_type();
+    let is_number = data_type.is_integer() || data_type.is_floating();
+    match physical_type {
+        PhysicalType::INT32 if data_type.is_integer() => Ok(DataType::Int32),
+        PhysicalType::INT64 if data_type.is_integer() => Ok(DataType::Int64),
+        PhysicalType::FLOAT if is_number => Ok(DataType::Float32),
+        PhysicalType::DOUBLE if is_number => Ok(DataType::Float64),
+        PhysicalType::BYTE_ARRAY => Ok(DataType::Utf8),
+        _ => Err(ParquetError::General(format!(
+            "Cannot write column {:?} of type {data_type} as parquet physical type {physical_type}",
+            field.name()
+        ))),
+    }
+}
+
+/// Types that can be listed in `type_priority`.
+#[derive(Clone, Copy, PartialEq)]
+enum InferType {
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    Timestamp,
+    String,
+}
+
+impl FromStr for InferType {
+    type Err = ParquetError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match normalize_variant(value).as_str() {
+            "bool" | "boolean" => Ok(Self::Boolean),
+            "int" | "integer" => Ok(Self::Integer),
+            "float" => Ok(Self::Float),
+            "date" => Ok(Self::Date),
+            "timestamp" => Ok(Self::Timestamp),
+            "string" => Ok(Self::String),
+            _ => Err(invalid_variant(
+                "type priority",
+                value,
+                "boolean, integer, float, date, timestamp, string",
+            )),
+        }
+    }
+}
+
+/// The value patterns of the arrow CSV inference, in the order of [`InferType`].
+static INFER_PATTERNS: LazyLock<RegexSet> = LazyLock::new(|| {
+    RegexSet::new([
+        r"(?i)^(true)$|^(false)$(?-i)",
+        r"^-?(\d+)$",
+        r"^-?((\d*\.\d+|\d+\.\d*)([eE][-+]?\d+)?|\d+([eE][-+]?\d+))$",
+        r"^\d{4}-\d\d-\d\d$",
+        r"^\d{4}-\d\d-\d\d[T ]\d\d:\d\d:\d\d(?:\.\d{1,9})?(?:[^\d].*)?$",
+    ])
+    .unwrap()
+});
+
+/// Gives each column of `schema` the first type in `priority` that all sampled values fit.
+fn prioritize_types(
+    schema: &Schema,
+    scan: &PreScan,
+    priority: &[String],
+    opts: &Opts,
+) -> Result<Schema, ParquetError> {
+    let priority = priority
+        .iter()
+        .map(|name| name.parse())
+        .collect::<Result<Vec<InferType>, _>>()?;
+
+    let fields = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let fits = match scan.fits.get(index) {
+                Some((true, fits)) if opts.infers_column(field.name()) => fits,
+                _ => return field.as_ref().clone(),
+            };
+            let data_type = priority
+                .iter()
+                .take_while(|infer_type| **infer_type != InferType::String)
+                .find(|infer_type| fits[**infer_type as usize])
+                .map_or(DataType::Utf8, |infer_type| match infer_type {
+                    InferType::Boolean => DataType::Boolean,
+                    InferType::Integer => DataType::Int64,
+                    InferType::Float => DataType::Float64,
+                    InferType::Date => DataType::Date32,
+                    // Keep the unit arrow picked from the fractional seconds.
+                    InferType::Timestamp => match field.data_type() {
+                        DataType::Timestamp(unit, tz) => DataType::Timestamp(*unit, tz.clone()),
+                        _ => DataType::Timestamp(TimeUnit::Second, None),
+                    },
+                    InferType::String => DataType::Utf8,
+                });
+            field.as_ref().clone().with_data_type(data_type)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// What the sampled values of a string column have in common, see [`integer_columns`].
+struct IntegerCandidate {
+    seen: bool,
+    lenient_integers: bool,
+    decimal_integers: bool,
+    exceeds_int64: bool,
+}
+
+impl Default for IntegerCandidate {
+    fn default() -> Self {
+        Self {
+            seen: false,
+            lenient_integers: true,
+            decimal_integers: true,
+            exceeds_int64: false,
+        }
+    }
+}
+
+impl IntegerCandidate {
+    fn update(&mut self, value: &str) {
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+        self.seen = true;
+        self.lenient_integers &= trimmed.parse::<i64>().is_ok();
+
+        // Decimals are parsed by arrow as is, so only plain digits with an optional minus qualify.
+        let digits = value.strip_prefix('-').unwrap_or(value);
+        let is_integer =
+            !digits.is_empty() && digits.len() <= 38 && digits.bytes().all(|b| b.is_ascii_digit());
+        self.decimal_integers &= is_integer;
+        self.exceeds_int64 |= is_integer && value.parse::<i64>().is_err();
+    }
+
+    fn is_lenient_integer(&self) -> bool {
+        self.seen && self.lenient_integers
+    }
+
+    fn is_large_integer(&self) -> bool {
+        self.seen && self.decimal_integers && self.exceeds_int64
+    }
+}
+
+/// Converts a batch as read to `schema`, parsing string columns that are integers in `schema`
+/// after trimming spaces and a leading `+`.
+fn conform_to_schema(
+    batch: RecordBatch,
+    schema: &SchemaRef,
+    decimal_rounding: Option<RoundingMode>,
+    number_formats: &[(usize, NumberFormat)],
+) -> Result<RecordBatch, ArrowError> {
+    if batch.schema() == *schema {
+        return Ok(batch);
+    }
+
+    let cast_options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .enumerate()
+        .map(|(index, (column, field))| {
+            if let Some((_, format)) = number_formats
+                .iter()
+                .find(|(format_index, _)| *format_index == index)
+            {
+                let strings = column.as_any().downcast_ref::<StringArray>().unwrap();
+                let normalized: StringArray = strings
+                    .iter()
+                    .map(|value| value.map(|value| format.normalize(value)))
+                    .collect();
+                return cast_with_options(&normalized, field.data_type(), &cast_options);
+            }
+
+            if field.data_type() == &DataType::Binary && column.data_type() == &DataType::Utf8 {
+                let strings = column.as_any().downcast_ref::<StringArray>().unwrap();
+                let binary: BinaryArray = strings
+                    .iter()
+                    .map(|value| value.map(decode_hex).transpose())
+                    .collect::<Result<_, _>>()?;
+                return Ok(Arc::new(binary) as ArrayRef);
+            }
+
+            if let (Some(mode), DataType::Decimal128(_, scale) | DataType::Decimal256(_, scale)) =
+                (decimal_rounding, field.data_type())
+            {
+                if column.data_type() == &DataType::Utf8 {
+                    let strings = column.as_any().downcast_ref::<StringArray>().unwrap();
+                    let rounded: StringArray = strings
+                        .iter()
+                        .map(|value| value.map(|value| round_decimal(value.trim(), *scale, mode)))
+                        .collect();
+                    return cast_with_options(&rounded, field.data_type(), &cast_options);
+                }
+            }
+
+            if !(field.data_type().is_integer() && column.data_type() == &DataType::Utf8) {
+                return Ok(column.clone());
+            }
+
+            let strings = column.as_any().downcast_ref::<StringArray>().unwrap();
+            let normalized: StringArray = strings
+                .iter()
+                .map(|value| {
+                    value.map(|value| {
+                        let value = value.trim();
+                        value.strip_prefix('+').unwrap_or(value)
+                    })
+                })
+                .collect();
+
+            cast_with_options(&normalized, field.data_type(), &cast_options)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// Rounds a decimal number written out in digits to `scale` fractional digits. Values that aren't
+/// plain decimal numbers are returned unchanged to be reported when they are parsed.
+fn round_decimal(value: &str, scale: i8, mode: RoundingMode) -> String {
+    let (sign, digits) = match value.strip_prefix(['-', '+']) {
+        Some(digits) => (&value[..1], digits),
+        None => ("", value),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let scale = scale.max(0) as usize;
+
+    let is_plain = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if fraction.len() <= scale || !is_plain(integer) || !is_plain(fraction) {
+        return value.to_string();
+    }
+
+    let (kept, dropped) = fraction.split_at(scale);
+    let mut kept_digits: Vec<u8> = format!("{integer}{kept}").into_bytes();
+    let first_dropped = dropped.as_bytes()[0];
+    let round_up = match mode {
+        RoundingMode::Truncate => false,
+        RoundingMode::HalfUp => first_dropped >= b'5',
+        RoundingMode::HalfEven => {
+            let last_odd = kept_digits
+                .last()
+                .is_some_and(|digit| (digit - b'0') % 2 == 1);
+            first_dropped > b'5'
+                || (first_dropped == b'5' && (dropped[1..].bytes().any(|d| d != b'0') || last_odd))
+        }
+    };
+
+    if round_up {
+        let mut carry = true;
+        for digit in kept_digits.iter_mut().rev() {
+            if *digit == b'9' {
+                *digit = b'0';
+            } else {
+                *digit += 1;
+                carry = false;
+                break;
+            }
+        }
+        if carry {
+            kept_digits.insert(0, b'1');
+        }
+    }
+
+    let kept_digits = String::from_utf8(kept_digits).unwrap();
+    let (integer, kept) = kept_digits.split_at(kept_digits.len() - scale);
+    let integer = if integer.is_empty() { "0" } else { integer };
+    if scale == 0 {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{kept}")
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, ArrowError> {
+    let value = value.trim();
+    let digit = |byte: u8| {
+        (byte as char).to_digit(16).ok_or_else(|| {
+            ArrowError::ParseError(format!("Error parsing \"{value}\" as hex-encoded WKB"))
+        })
+    };
+
+    if !value.len().is_multiple_of(2) {
+        return Err(ArrowError::ParseError(format!(
+            "Error parsing \"{value}\" as hex-encoded WKB, odd number of digits"
+        )));
+    }
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| Ok((digit(pair[0])? * 16 + digit(pair[1])?) as u8))
+        .collect()
+}
+
+/// Replaces NaN values in float columns with nulls.
+fn replace_nans_with_nulls(batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+    fn nans<T: ArrowPrimitiveType>(
+        column: &ArrayRef,
+        is_nan: fn(T::Native) -> bool,
+    ) -> BooleanArray {
+        column
+            .as_primitive::<T>()
+            .iter()
+            .map(|value| value.map(is_nan))
+            .collect()
+    }
+
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| {
+            let nans = match column.data_type() {
+                DataType::Float16 => nans::<Float16Type>(column, |value| value.is_nan()),
+                DataType::Float32 => nans::<Float32Type>(column, f32::is_nan),
+                DataType::Float64 => nans::<Float64Type>(column, f64::is_nan),
+                _ => return Ok(column.clone()),
+            };
+            nullif(column, &nans)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(batch.schema(), columns)
+}
+
+/// Replaces `\n` and `\t` in `value` with a newline and a tab, and `\\` with a single
+/// backslash. Other backslashes are kept.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Unescapes the values of string columns, see [`unescape`].
+fn unescape_newlines(batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let columns = batch
+        .columns()
+        .iter()
+        .map(
+            |column| match column.as_any().downcast_ref::<StringArray>() {
+                Some(strings) if strings.iter().flatten().any(|value| value.contains('\\')) => {
+                    let unescaped: StringArray =
+                        strings.iter().map(|value| value.map(unescape)).collect();
+                    Arc::new(unescaped) as ArrayRef
+                }
+                _ => column.clone(),
+            },
+        )
+        .collect();
+    RecordBatch::try_new(batch.schema(), columns)
+}
+
+fn replace_empty_strings_with_nulls(batch: RecordBatch) -> arrow::error::Result<RecordBatch> {
+    let mut new_columns: Vec<ArrayRef> = Vec::new();
+
+    // Iterate over each column in the batch
+    for i in 0..batch.num_columns() {
+        let column = batch.column(i);
+        let schema = batch.schema();
+        let field = schema.field(i);
+
+        // Check if the column is a nullable string type
+        if matches!(field.data_type(), &DataType::Utf8) && field.is_nullable() {
+            // Create a new column with empty strings replaced by nulls
+            let string_array = column.as_any().downcast_ref::<StringArray>().unwrap();
+            // let mut builder = LargeStringArray::into_builder(string_array.len()).unwrap();
+            let mut builder: GenericByteBuilder<GenericStringType<i32>> = GenericByteBuilder::new();
+
+            for j in 0..string_array.len() {
+                if string_array.is_null(j) || string_array.value(j).is_empty() {
+                    builder.append_null();
+                } else {
+                    builder.append_value(string_array.value(j));
+                }
+            }
+
+            new_columns.push(Arc::new(builder.finish()) as ArrayRef);
+        } else {
+            // For non-string or non-nullable fields, use the original column
+            new_columns.push(column.clone());
+        }
+    }
+
+    // Create a new RecordBatch with updated columns
+    let new_batch = RecordBatch::try_new(batch.schema(), new_columns)?;
+
+    Ok(new_batch)
+}
+
+/// Rewrites CSV records to a fixed number of fields according to a [`RaggedMode`], first dropping
+/// the empty field after a trailing delimiter if `trailing_delimiter` is set.
+struct RaggedReader<R: Read> {
+    reader: csv::Reader<R>,
+    writer: csv::WriterBuilder,
+    record: csv::ByteRecord,
+    fields: Option<usize>,
+    pad: bool,
+    truncate: bool,
+    trailing_delimiter: bool,
+    header_records: usize,
+    row: usize,
+    padded: Option<Rc<RefCell<BTreeMap<usize, usize>>>>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> RaggedReader<R> {
+    const CHUNK_SIZE: usize = 8192;
+
+    fn new(
+        reader: R,
+        delimiter: u8,
+        escape: u8,
+        mode: &RaggedMode,
+        fields: Option<usize>,
+        trailing_delimiter: bool,
+    ) -> Self {
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .escape(Some(escape))
+            .quote(b'"')
+            .from_reader(reader);
+        let mut writer = csv::WriterBuilder::new();
+        writer.flexible(true).delimiter(delimiter).quote(b'"');
+
+        Self {
+            reader,
+            writer,
+            record: csv::ByteRecord::new(),
+            fields,
+            pad: !matches!(mode, RaggedMode::Fail),
+            truncate: matches!(mode, RaggedMode::Truncate),
+            trailing_delimiter,
+            header_records: 0,
+            row: 0,
+            padded: None,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Records the first padded column of each padded data row in `padded`, counting data rows
+    /// from zero after `header_records` records, so the padding can be read as null even where
+    /// empty fields are read as empty strings.
+    fn record_padding(
+        mut self,
+        header_records: usize,
+        padded: Option<Rc<RefCell<BTreeMap<usize, usize>>>>,
+    ) -> Self {
+        self.header_records = header_records;
+        self.padded = padded;
+        self
+    }
+
+    fn fill_buffer(&mut self) -> std::io::Result<()> {
+        let mut writer = self
+            .writer
+            .from_writer(Vec::with_capacity(Self::CHUNK_SIZE));
+
+        while writer.get_ref().len() < Self::CHUNK_SIZE
+            && self.reader.read_byte_record(&mut self.record)?
+        {
+            let len = self.record.len();
+            if self.trailing_delimiter && len > 1 && self.record[len - 1].is_empty() {
+                self.record.truncate(len - 1);
+            }
+
+            if self.pad {
+                let fields = *self.fields.get_or_insert(self.record.len());
+                if self.record.len() > fields && self.truncate {
+                    self.record.truncate(fields);
+                }
+                if self.record.len() < fields && self.row >= self.header_records {
+                    if let Some(ref padded) = self.padded {
+                        padded
+                            .borrow_mut()
+                            .insert(self.row - self.header_records, self.record.len());
+                    }
+                }
+                while self.record.len() < fields {
+                    self.record.push_field(b"");
+                }
+            }
+            self.row += 1;
+
+            writer.write_byte_record(&self.record)?;
+            writer.flush()?;
+        }
+
+        self.buffer = writer.into_inner().map_err(|error| error.into_error())?;
+        self.pos = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for RaggedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.buffer.len() {
+            self.fill_buffer()?;
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+/// Passes CSV bytes through unchanged and fails once a record ends differently from the first
+/// record, counting the header as the first record.
+struct LineEndingCheck<R: Read> {
+    reader: R,
+    escape: u8,
+    quoted: bool,
+    escaped: bool,
+    pending_cr: bool,
+    record: usize,
+    first: Option<&'static str>,
+}
+
+impl<R: Read> LineEndingCheck<R> {
+    fn new(reader: R, escape: u8) -> Self {
+        Self {
+            reader,
+            escape,
+            quoted: false,
+            escaped: false,
+            pending_cr: false,
+            record: 1,
+            first: None,
+        }
+    }
+
+    fn record_end(&mut self, ending: &'static str) -> std::io::Result<()> {
+        let first = *self.first.get_or_insert(ending);
+        if first != ending {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Record {} ends with {ending:?} but earlier records end with {first:?}",
+                    self.record
+                ),
+            ));
+        }
+        self.record += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LineEndingCheck<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        if len == 0 && self.pending_cr {
+            self.pending_cr = false;
+            self.record_end("\r")?;
+        }
+
+        for &byte in &buf[..len] {
+            if self.pending_cr {
+                self.pending_cr = false;
+                if byte == b'\n' {
+                    self.record_end("\r\n")?;
+                    continue;
+                }
+                self.record_end("\r")?;
+            }
+
+            if self.quoted {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == self.escape && self.escape != b'"' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.quoted = false;
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => self.quoted = true,
+                b'\r' => self.pending_cr = true,
+                b'\n' => self.record_end("\n")?,
+                _ => {}
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+/// Rewrites quotes escaped with `escape` to doubled quotes. A second quote after the end of a
+/// quoted field is part of the value, as in the CSV reader without doubled quotes, so the field
+/// stays quoted until its end. Fields keep whether they are quoted and line breaks stay in place.
+struct QuoteNormalizer<R: Read> {
+    reader: R,
+    delimiter: u8,
+    escape: u8,
+    state: QuoteState,
+    chunk: Vec<u8>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+#[derive(Clone, Copy)]
+enum QuoteState {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    Escaped,
+    /// After a quote in a quoted field, whose closing quote is written once the field ends.
+    Closed,
+}
+
+impl<R: Read> QuoteNormalizer<R> {
+    const CHUNK_SIZE: usize = 8192;
+
+    fn new(reader: R, delimiter: u8, escape: u8) -> Self {
+        Self {
+            reader,
+            delimiter,
+            escape,
+            state: QuoteState::FieldStart,
+            chunk: vec![0; Self::CHUNK_SIZE],
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn rewrite(&mut self, byte: u8) {
+        let ends_field = byte == self.delimiter || byte == b'\n' || byte == b'\r';
+        self.state = match self.state {
+            QuoteState::FieldStart | QuoteState::Unquoted if ends_field => {
+                self.buffer.push(byte);
+                QuoteState::FieldStart
+            }
+            QuoteState::FieldStart if byte == b'"' => {
+                self.buffer.push(byte);
+                QuoteState::Quoted
+            }
+            QuoteState::FieldStart | QuoteState::Unquoted => {
+                self.buffer.push(byte);
+                QuoteState::Unquoted
+            }
+            QuoteState::Quoted if byte == b'"' => QuoteState::Closed,
+            QuoteState::Quoted if byte == self.escape => QuoteState::Escaped,
+            QuoteState::Quoted => {
+                self.buffer.push(byte);
+                QuoteState::Quoted
+            }
+            QuoteState::Escaped => {
+                if byte == b'"' {
+                    self.buffer.push(b'"');
+                }
+                self.buffer.push(byte);
+                QuoteState::Quoted
+            }
+            QuoteState::Closed if ends_field => {
+                self.buffer.extend([b'"', byte]);
+                QuoteState::FieldStart
+            }
+            QuoteState::Closed => {
+                if byte == b'"' {
+                    self.buffer.push(b'"');
+                }
+                self.buffer.push(byte);
+                QuoteState::Closed
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for QuoteNormalizer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.buffer.len() {
+            self.buffer.clear();
+            self.pos = 0;
+            let len = self.reader.read(&mut self.chunk)?;
+            if len == 0 {
+                // The last field may not end with a line break.
+                if let QuoteState::Closed =
+                    std::mem::replace(&mut self.state, QuoteState::FieldStart)
+                {
+                    self.buffer.push(b'"');
+                }
+                if self.buffer.is_empty() {
+                    return Ok(0);
+                }
+                break;
+            }
+            for index in 0..len {
+                self.rewrite(self.chunk[index]);
+            }
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+/// Rewrites `\r\n` and `\r` record endings to `\n`, leaving quoted fields untouched.
+struct NewlineNormalizer<R: Read> {
+    reader: R,
+    escape: u8,
+    quoted: bool,
+    escaped: bool,
+    after_cr: bool,
+}
+
+impl<R: Read> NewlineNormalizer<R> {
+    fn new(reader: R, escape: u8) -> Self {
+        Self {
+            reader,
+            escape,
+            quoted: false,
+            escaped: false,
+            after_cr: false,
+        }
+    }
+}
+
+impl<R: Read> Read for NewlineNormalizer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let len = self.reader.read(buf)?;
+            if len == 0 {
+                return Ok(0);
+            }
+
+            // The output is never longer than the input, so bytes are rewritten in place.
+            let mut out = 0;
+            for index in 0..len {
+                let byte = buf[index];
+                let after_cr = std::mem::take(&mut self.after_cr);
+
+                if self.quoted {
+                    if self.escaped {
+                        self.escaped = false;
+                    } else if byte == self.escape && self.escape != b'"' {
+                        self.escaped = true;
+                    } else if byte == b'"' {
+                        self.quoted = false;
+                    }
+                    buf[out] = byte;
+                    out += 1;
+                    continue;
+                }
+
+                match byte {
+                    b'\n' if after_cr => continue,
+                    b'\r' => {
+                        self.after_cr = true;
+                        buf[out] = b'\n';
+                    }
+                    b'"' => {
+                        self.quoted = true;
+                        buf[out] = byte;
+                    }
+                    _ => buf[out] = byte,
+                }
+                out += 1;
+            }
+
+            // A read of only the `\n` of a `\r\n` must not look like the end of the input.
+            if out > 0 {
+                return Ok(out);
+            }
+        }
+    }
+}
+
+/// Passes CSV bytes through unchanged while recording the `(row, column)` of every quoted empty
+/// field, counting data rows from zero after `header_rows` rows.
+struct QuotedEmptyScanner<R: Read> {
+    reader: R,
+    delimiter: u8,
+    escape: u8,
+    header_rows: usize,
+    state: ScanState,
+    row: usize,
+    column: usize,
+    quoted_empty: Rc<RefCell<BTreeSet<(usize, usize)>>>,
+}
+
+#[derive(Clone, Copy)]
+enum ScanState {
+    FieldStart,
+    Unquoted,
+    Quoted { empty: bool },
+    QuotedEscape,
+    QuoteInQuoted { empty: bool },
+    AfterQuoted { empty: bool },
+}
+
+impl<R: Read> QuotedEmptyScanner<R> {
+    fn new(
+        reader: R,
+        delimiter: u8,
+        escape: u8,
+        header_rows: usize,
+        quoted_empty: Rc<RefCell<BTreeSet<(usize, usize)>>>,
+    ) -> Self {
+        Self {
+            reader,
+            delimiter,
+            escape,
+            header_rows,
+            state: ScanState::FieldStart,
+            row: 0,
+            column: 0,
+            quoted_empty,
+        }
+    }
+
+    fn end_field(&mut self, quoted_empty: bool) {
+        if quoted_empty && self.row >= self.header_rows {
+            self.quoted_empty
+                .borrow_mut()
+                .insert((self.row - self.header_rows, self.column));
+        }
+        self.column += 1;
+    }
+
+    fn end_row(&mut self) {
+        self.row += 1;
+        self.column = 0;
+    }
+
+    fn scan(&mut self, byte: u8) {
+        self.state = match self.state {
+            // Empty lines are skipped by the CSV reader and don't count as rows.
+            ScanState::FieldStart if byte == b'\n' && self.column == 0 => ScanState::FieldStart,
+            ScanState::FieldStart | ScanState::Unquoted if byte == b'\n' => {
+                self.end_field(false);
+                self.end_row();
+                ScanState::FieldStart
+            }
+            ScanState::FieldStart | ScanState::Unquoted if byte == self.delimiter => {
+                self.end_field(false);
+                ScanState::FieldStart
+            }
+            ScanState::FieldStart if byte == b'"' => ScanState::Quoted { empty: true },
+            ScanState::FieldStart if byte == b'\r' => ScanState::FieldStart,
+            ScanState::FieldStart | ScanState::Unquoted => ScanState::Unquoted,
+            ScanState::Quoted { empty } if byte == b'"' => ScanState::QuoteInQuoted { empty },
+            ScanState::Quoted { .. } if byte == self.escape => ScanState::QuotedEscape,
+            ScanState::Quoted { .. } | ScanState::QuotedEscape => {
+                ScanState::Quoted { empty: false }
+            }
+            ScanState::QuoteInQuoted { .. } if byte == b'"' => ScanState::Quoted { empty: false },
+            ScanState::QuoteInQuoted { empty } | ScanState::AfterQuoted { empty } => {
+                if byte == self.delimiter {
+                    self.end_field(empty);
+                    ScanState::FieldStart
+                } else if byte == b'\n' {
+                    self.end_field(empty);
+                    self.end_row();
+                    ScanState::FieldStart
+                } else if byte == b'\r' {
+                    ScanState::AfterQuoted { empty }
+                } else {
+                    ScanState::AfterQuoted { empty: false }
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for QuotedEmptyScanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        if len == 0 {
+            // The last record may not end with a line break.
+            if let ScanState::QuoteInQuoted { empty } | ScanState::AfterQuoted { empty } =
+                self.state
+            {
+                self.end_field(empty);
+            }
+            self.state = ScanState::FieldStart;
+        }
+        for &byte in &buf[..len] {
+            self.scan(byte);
+        }
+        Ok(len)
+    }
+}
+
+/// Rewrites the nulls arrow reads for empty fields in string columns according to whether they
+/// were quoted, using the positions found by a [`QuotedEmptyScanner`]. Fields added by padding
+/// ragged rows stay null.
+struct EmptyFields<I> {
+    batches: I,
+    columns: Vec<usize>,
+    quoted_empty: Rc<RefCell<BTreeSet<(usize, usize)>>>,
+    padded: Rc<RefCell<BTreeMap<usize, usize>>>,
+    quoted_empty_as_null: bool,
+    empty_as_null: bool,
+    offset: usize,
+}
+
+impl<I: Iterator<Item = Result<RecordBatch, ArrowError>>> Iterator for EmptyFields<I> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.batches.next()? {
+            Ok(batch) => batch,
+            Err(error) => return Some(Err(error)),
+        };
+        if self.columns.is_empty() {
+            return Some(Ok(batch));
+        }
+
+        let offset = self.offset;
+        self.offset += batch.num_rows();
+
+        let mut quoted_empty = self.quoted_empty.borrow_mut();
+        let mut padded = self.padded.borrow_mut();
+        let mut columns = batch.columns().to_vec();
+        for &index in &self.columns {
+            let column = columns[index].as_any().downcast_ref::<StringArray>();
+            let Some(column) = column.filter(|column| column.null_count() > 0) else {
+                continue;
+            };
+            let values: StringArray = column
+                .iter()
+                .enumerate()
+                .map(|(row, value)| {
+                    if value.is_some() {
+                        return value;
+                    }
+                    if padded
+                        .get(&(offset + row))
+                        .is_some_and(|&first| index >= first)
+                    {
+                        return None;
+                    }
+                    let null = if quoted_empty.contains(&(offset + row, index)) {
+                        self.quoted_empty_as_null
+                    } else {
+                        self.empty_as_null
+                    };
+                    (!null).then_some("")
+                })
+                .collect();
+            columns[index] = Arc::new(values);
+        }
+
+        // Positions before the next batch are no longer needed.
+        *quoted_empty = quoted_empty.split_off(&(self.offset, 0));
+        *padded = padded.split_off(&self.offset);
+
+        Some(RecordBatch::try_new(batch.schema(), columns))
+    }
+}
+
+/// Name of the column added by [`Opts::preserve_line_numbers`].
+const LINE_COLUMN: &str = "__line__";
+
+/// Passes CSV bytes through unchanged while recording the line each record starts on, counting
+/// from one. Line breaks in quoted fields count as lines but don't end the record.
+struct LineNumberScanner<R: Read> {
+    reader: R,
+    escape: u8,
+    quoted: bool,
+    escaped: bool,
+    after_cr: bool,
+    line: u64,
+    record_start: Option<u64>,
+    line_starts: Rc<RefCell<VecDeque<u64>>>,
+}
+
+impl<R: Read> LineNumberScanner<R> {
+    fn new(reader: R, escape: u8, line_starts: Rc<RefCell<VecDeque<u64>>>) -> Self {
+        Self {
+            reader,
+            escape,
+            quoted: false,
+            escaped: false,
+            after_cr: false,
+            line: 1,
+            record_start: None,
+            line_starts,
+        }
+    }
+}
+
+impl<R: Read> Read for LineNumberScanner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+        let mut line_starts = self.line_starts.borrow_mut();
+        if len == 0 {
+            line_starts.extend(self.record_start.take());
+        }
+
+        for &byte in &buf[..len] {
+            if byte == b'\r' || byte == b'\n' {
+                // `\r\n` is one line break.
+                if !(byte == b'\n' && self.after_cr) {
+                    self.line += 1;
+                }
+                self.after_cr = byte == b'\r';
+                if !self.quoted {
+                    line_starts.extend(self.record_start.take());
+                }
+                continue;
+            }
+            self.after_cr = false;
+            self.record_start.get_or_insert(self.line);
+
+            if self.quoted {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == self.escape && self.escape != b'"' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.quoted = false;
+                }
+            } else if byte == b'"' {
+                self.quoted = true;
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+/// Appends the line numbers recorded by [`LineNumberScanner`] to each batch as the last column,
+/// after skipping the header records. Batches pass through unchanged without line numbers.
+struct LineNumbers<I> {
+    batches: I,
+    line_starts: Option<Rc<RefCell<VecDeque<u64>>>>,
+    header_records: usize,
+}
+
+impl<I: Iterator<Item = Result<RecordBatch, ArrowError>>> Iterator for LineNumbers<I> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.batches.next()? {
+            Ok(batch) => batch,
+            Err(error) => return Some(Err(error)),
+        };
+        let Some(ref line_starts) = self.line_starts else {
+            return Some(Ok(batch));
+        };
+
+        let mut line_starts = line_starts.borrow_mut();
+        let header_records = std::mem::take(&mut self.header_records).min(line_starts.len());
+        line_starts.drain(..header_records);
+        if line_starts.len() < batch.num_rows() {
+            return Some(Err(ArrowError::ComputeError(
+                "Could not match rows to input lines".to_string(),
+            )));
+        }
+        let lines: UInt64Array = line_starts.drain(..batch.num_rows()).collect();
+
+        let mut fields = batch.schema().fields().to_vec();
+        fields.push(Arc::new(Field::new(LINE_COLUMN, DataType::UInt64, false)));
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(lines));
+
+        Some(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns))
+    }
+}
+
+/// Yields only the rows in `start..end` of the batches, slicing batches at the boundaries.
+struct RowRange<I> {
+    batches: I,
+    start: usize,
+    end: usize,
+    offset: usize,
+}
+
+impl<I> RowRange<I> {
+    fn new(batches: I, start: usize, end: usize) -> Self {
+        Self {
+            batches,
+            start,
+            end,
+            offset: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<RecordBatch, ArrowError>>> Iterator for RowRange<I> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.end {
+            let batch = match self.batches.next()? {
+                Ok(batch) => batch,
+                Err(error) => return Some(Err(error)),
+            };
+            let batch_start = self.offset;
+            self.offset += batch.num_rows();
+
+            if self.offset <= self.start {
+                continue;
+            }
+            let from = self.start.saturating_sub(batch_start);
+            let to = self.end.min(self.offset) - batch_start;
+            if from == 0 && to == batch.num_rows() {
+                return Some(Ok(batch));
+            }
+            return Some(Ok(batch.slice(from, to - from)));
+        }
+
+        None
+    }
+}
+
+/// Combines the first rows of a CSV into a single header row, passing the remaining records through.
+struct HeaderRowsReader<R: Read> {
+    reader: csv::Reader<R>,
+    writer: csv::WriterBuilder,
+    record: csv::ByteRecord,
+    header_rows: usize,
+    separator: Vec<u8>,
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> HeaderRowsReader<R> {
+    const CHUNK_SIZE: usize = 8192;
+
+    fn new(reader: R, delimiter: u8, escape: u8, header_rows: usize, separator: &str) -> Self {
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .escape(Some(escape))
+            .quote(b'"')
+            .from_reader(reader);
+        let mut writer = csv::WriterBuilder::new();
+        writer.flexible(true).delimiter(delimiter).quote(b'"');
+
+        Self {
+            reader,
+            writer,
+            record: csv::ByteRecord::new(),
+            header_rows,
+            separator: separator.as_bytes().to_vec(),
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reads the header rows and joins the non-empty parts of each column with the separator.
+    fn combined_header(&mut self) -> std::io::Result<csv::ByteRecord> {
+        let mut names: Vec<Vec<u8>> = Vec::new();
+
+        for _ in 0..self.header_rows {
+            if !self.reader.read_byte_record(&mut self.record)? {
+                break;
+            }
+            if names.len() < self.record.len() {
+                names.resize(self.record.len(), Vec::new());
+            }
+            for (name, part) in names.iter_mut().zip(self.record.iter()) {
+                let part = part.trim_ascii();
+                if part.is_empty() {
+                    continue;
+                }
+                if !name.is_empty() {
+                    name.extend_from_slice(&self.separator);
+                }
+                name.extend_from_slice(part);
+            }
+        }
+
+        Ok(names.iter().collect())
+    }
+
+    fn fill_buffer(&mut self) -> std::io::Result<()> {
+        let mut writer = self
+            .writer
+            .from_writer(Vec::with_capacity(Self::CHUNK_SIZE));
+
+        if self.header_rows > 0 {
+            let header = self.combined_header()?;
+            self.header_rows = 0;
+            if !header.is_empty() {
+                writer.write_byte_record(&header)?;
+            }
+        }
+
+        while writer.get_ref().len() < Self::CHUNK_SIZE
+            && self.reader.read_byte_record(&mut self.record)?
+        {
+            writer.write_byte_record(&self.record)?;
+            writer.flush()?;
+        }
+
+        writer.flush()?;
+        self.buffer = writer.into_inner().map_err(|error| error.into_error())?;
+        self.pos = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for HeaderRowsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.buffer.len() {
+            self.fill_buffer()?;
+        }
+
+        let len = buf.len().min(self.buffer.len() - self.pos);
+        buf[..len].copy_from_slice(&self.buffer[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+/// Reads footer metadata from a file with a JSON object of string keys and values.
+fn read_metadata_file(path: &Path) -> Result<Vec<(String, String)>, ParquetError> {
+    let metadata_file = File::open(path).map_err(|error| {
+        ParquetError::General(format!(
+            "Error opening metadata file: {path:?}, message: {error}"
+        ))
+    })?;
+    let metadata: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_reader(BufReader::new(metadata_file)).map_err(|error| {
+            ParquetError::General(format!("Error reading metadata json: {error}"))
+        })?;
+
+    metadata
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => Ok((key, value)),
+            value => Err(ParquetError::General(format!(
+                "Metadata file {path:?} has a value that is not a string for {key:?}: {value}"
+            ))),
+        })
+        .collect()
+}
+
+/// Describes the columns added, removed, or with a different type in `schema` compared to
+/// `baseline`, matched by name.
+fn schema_drift(baseline: &Schema, schema: &Schema) -> Vec<String> {
+    let mut drift: Vec<String> = baseline
+        .fields()
+        .iter()
+        .filter(|field| schema.field_with_name(field.name()).is_err())
+        .map(|field| format!("column {:?} was removed", field.name()))
+        .collect();
+
+    for field in schema.fields() {
+        match baseline.field_with_name(field.name()) {
+            Err(_) => drift.push(format!(
+                "column {:?} was added as {}",
+                field.name(),
+                field.data_type()
+            )),
+            Ok(previous) if previous.data_type() != field.data_type() => drift.push(format!(
+                "column {:?} changed from {} to {}",
+                field.name(),
+                previous.data_type(),
+                field.data_type()
+            )),
+            Ok(_) => {}
+        }
+    }
+
+    drift
+}
+
+/// Reads an Arrow schema in JSON format from `path`, e.g. to check a schema file before using it
+/// for [`Opts::schema`]. Errors include the location of invalid JSON or schema fields.
+pub fn validate_schema_file(path: &Path) -> Result<Schema, ParquetError> {
+    let schema_file = File::open(path).map_err(|error| {
+        ParquetError::General(format!(
+            "Error opening schema file: {path:?}, message: {error}"
+        ))
+    })?;
+
+    serde_json::from_reader(BufReader::new(schema_file)).map_err(|error| {
+        let problem = match error.classify() {
+            Category::Io => "could not be read",
+            Category::Syntax | Category::Eof => "is not valid JSON",
+            Category::Data => "is not a valid Arrow schema",
+        };
+        ParquetError::General(format!("Schema file {path:?} {problem}: {error}"))
+    })
+}
+
+/// Parses a compact column spec such as `id:int64!,name:string,ts:timestamp[ms]` into a schema.
+pub fn parse_schema_spec(spec: &str) -> Result<Schema, ParquetError> {
+    let mut fields = Vec::new();
+
+    for column in split_top_level(spec, ',') {
+        let column = column.trim();
+        let (name, data_type) = column.split_once(':').ok_or_else(|| {
+            ParquetError::General(format!(
+                "Invalid column spec '{column}', expected 'name:type'"
+            ))
+        })?;
+
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ParquetError::General(format!(
+                "Invalid column spec '{column}', missing column name"
+            )));
+        }
+
+        let data_type = data_type.trim();
+        let (data_type, nullable) = match data_type.strip_suffix('!') {
+            Some(data_type) => (data_type.trim_end(), false),
+            None => (data_type, true),
+        };
+
+        fields.push(Field::new(
+            name,
+            parse_spec_data_type(data_type).map_err(|error| {
+                ParquetError::General(format!("Invalid type for column '{name}': {error}"))
+            })?,
+            nullable,
+        ));
+    }
+
+    Ok(Schema::new(fields))
+}
+
+fn parse_spec_data_type(data_type: &str) -> Result<DataType, String> {
+    let (base, args) = match data_type.find(['(', '[']) {
+        Some(start) => {
+            let close = if data_type.as_bytes()[start] == b'(' {
+                ')'
+            } else {
+                ']'
+            };
+            let args = data_type[start + 1..]
+                .strip_suffix(close)
+                .ok_or_else(|| format!("unterminated arguments in '{data_type}'"))?;
+            (&data_type[..start], Some(args))
+        }
+        None => (data_type, None),
+    };
+
+    let base = base.trim().to_lowercase();
+    let simple = match base.as_str() {
+        "bool" | "boolean" => Some(DataType::Boolean),
+        "int8" => Some(DataType::Int8),
+        "int16" => Some(DataType::Int16),
+        "int32" | "int" => Some(DataType::Int32),
+        "int64" | "long" => Some(DataType::Int64),
+        "uint8" => Some(DataType::UInt8),
+        "uint16" => Some(DataType::UInt16),
+        "uint32" => Some(DataType::UInt32),
+        "uint64" => Some(DataType::UInt64),
+        "float16" => Some(DataType::Float16),
+        "float32" | "float" => Some(DataType::Float32),
+        "float64" | "double" => Some(DataType::Float64),
+        "string" | "utf8" => Some(DataType::Utf8),
+        "large_string" | "large_utf8" => Some(DataType::LargeUtf8),
+        "binary" => Some(DataType::Binary),
+        "date32" | "date" => Some(DataType::Date32),
+        "date64" => Some(DataType::Date64),
+        _ => None,
+    };
+
+    if let Some(simple) = simple {
+        return match args {
+            Some(_) => Err(format!("type '{base}' does not take arguments")),
+            None => Ok(simple),
+        };
+    }
+
+    match base.as_str() {
+        "timestamp" => {
+            let args = args.unwrap_or("us");
+            let (unit, tz) = match args.split_once(',') {
+                Some((unit, tz)) => (unit.trim(), Some(tz.trim())),
+                None => (args.trim(), None),
+            };
+            let unit = match unit {
+                "s" => TimeUnit::Second,
+                "ms" => TimeUnit::Millisecond,
+                "us" => TimeUnit::Microsecond,
+                "ns" => TimeUnit::Nanosecond,
+                _ => {
+                    return Err(format!(
+                        "unknown time unit '{unit}', expected s, ms, us, or ns"
+                    ))
+                }
+            };
+            Ok(DataType::Timestamp(unit, tz.map(Into::into)))
+        }
+        "decimal" | "decimal128" => {
+            let args = args.ok_or_else(|| "decimal requires precision and scale".to_string())?;
+            let (precision, scale) = args
+                .split_once(',')
+                .ok_or_else(|| format!("expected 'decimal(precision,scale)', got '{data_type}'"))?;
+            let precision = precision
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| format!("invalid decimal precision '{}'", precision.trim()))?;
+            let scale = scale
+                .trim()
+                .parse::<i8>()
+                .map_err(|_| format!("invalid decimal scale '{}'", scale.trim()))?;
+            if precision == 0 || precision > 38 {
+                return Err(format!("decimal precision {precision} must be in 1..=38"));
+            }
+            Ok(DataType::Decimal128(precision, scale))
+        }
+        _ => Err(format!("unknown type '{base}'")),
+    }
+}
+
+/// Splits on `separator`, ignoring separators nested inside parentheses or brackets.
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, c) in input.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                parts.push(&input[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_schema_spec_types() {
+        let schema = parse_schema_spec(
+            "id:int64!, name:string, ts:timestamp[ms], price:decimal(10,2), ok:bool, day:date",
+        )
+        .unwrap();
+
+        let fields: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                (
+                    field.name().as_str(),
+                    field.data_type().clone(),
+                    field.is_nullable(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("id", DataType::Int64, false),
+                ("name", DataType::Utf8, true),
+                ("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+                ("price", DataType::Decimal128(10, 2), true),
+                ("ok", DataType::Boolean, true),
+                ("day", DataType::Date32, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_schema_spec_timestamp_with_time_zone() {
+        let schema = parse_schema_spec("ts:timestamp[us, UTC]").unwrap();
+        assert_eq!(
+            schema.field(0).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+    }
+
+    #[test]
+    fn parse_schema_spec_errors() {
+        let error = |spec: &str| parse_schema_spec(spec).unwrap_err().to_string();
+
+        assert!(error("id").contains("expected 'name:type'"));
+        assert!(error(":int64").contains("missing column name"));
+        assert!(error("id:integer128").contains("unknown type 'integer128'"));
+        assert!(error("price:decimal(10,2").contains("unterminated arguments"));
+        assert!(error("price:decimal(40,2)").contains("must be in 1..=38"));
+        assert!(error("ts:timestamp[m]").contains("unknown time unit 'm'"));
+        assert!(error("id:int64(8)").contains("does not take arguments"));
+    }
+
+    #[test]
+    fn round_decimal_modes() {
+        let round = |value, mode| round_decimal(value, 2, mode);
+
+        assert_eq!(round("1.005", RoundingMode::HalfUp), "1.01");
+        assert_eq!(round("1.005", RoundingMode::HalfEven), "1.00");
+        assert_eq!(round("1.015", RoundingMode::HalfEven), "1.02");
+        assert_eq!(round("1.0051", RoundingMode::HalfEven), "1.01");
+        assert_eq!(round("1.009", RoundingMode::Truncate), "1.00");
+        assert_eq!(round("-1.005", RoundingMode::HalfUp), "-1.01");
+        assert_eq!(round("9.995", RoundingMode::HalfUp), "10.00");
+        assert_eq!(round("1.5", RoundingMode::HalfUp), "1.5");
+        assert_eq!(round("1e3", RoundingMode::HalfUp), "1e3");
+    }
+
+    #[test]
+    fn verify_output_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.csv");
+        let output = dir.path().join("output.parquet");
+        fs::write(&input, "a\n1\n2\n").unwrap();
+        convert(Opts::new(input, output.clone())).unwrap();
+
+        verify_output(&output, 2, true).unwrap();
+        let error = verify_output(&output, 3, false).unwrap_err();
+        assert!(error.to_string().contains("wrote 3 rows but read back 2"));
+
+        // Cut off the footer as if the write had been interrupted.
+        let bytes = fs::read(&output).unwrap();
+        fs::write(&output, &bytes[..bytes.len() - 8]).unwrap();
+        assert!(verify_output(&output, 2, false).is_err());
+    }
+
+    #[test]
+    fn estimate_output_size_extrapolates() {
+        let csv: String = std::iter::once("id,name\n".to_string())
+            .chain((0..150_000).map(|row| format!("{row},name {}\n", row % 1000)))
+            .collect();
+        let schema = Arc::new(parse_schema_spec("id:int64,name:string").unwrap());
+        let opts = Opts::new(PathBuf::new(), PathBuf::new());
+        let props = opts.writer_properties_for_schema(&schema);
+
+        let reader = ReaderBuilder::new(schema.clone())
+            .with_header(true)
+            .build(csv.as_bytes())
+            .unwrap();
+        let pipeline = BatchPipeline::try_new(schema.clone(), None, &opts).unwrap();
+        let estimate = estimate_output_size(reader, &pipeline, props.clone()).unwrap();
+
+        let reader = ReaderBuilder::new(schema.clone())
+            .with_header(true)
+            .build(csv.as_bytes())
+            .unwrap();
+        let mut sink = ParquetSink::try_new(Vec::new(), schema, props).unwrap();
+        for batch in reader {
+            sink.write_batch(&batch.unwrap()).unwrap();
+        }
+        let actual = sink.into_inner().unwrap().len() as f64;
+
+        let error = (estimate as f64 - actual).abs() / actual;
+        assert!(error < 0.2, "estimated {estimate} bytes, wrote {actual}");
+    }
+
+    #[test]
+    fn schema_json_compact() {
+        let schema = parse_schema_spec("id:int64,name:string").unwrap();
+
+        let compact = schema_json(&schema, true);
+        assert!(!compact.contains('\n'));
+        let pretty = schema_json(&schema, false);
+        assert!(pretty.contains('\n'));
+
+        let parsed: Schema = serde_json::from_str(&compact).unwrap();
+        assert_eq!(parsed, serde_json::from_str::<Schema>(&pretty).unwrap());
+        assert_eq!(parsed, schema);
+    }
+}
@@ -0,0 +1,105 @@
+#![allow(dead_code)]
+
+use arrow::{array::RecordBatch, compute::concat_batches};
+use csv2parquet::Opts;
+use parquet::{
+    arrow::arrow_reader::ParquetRecordBatchReaderBuilder, errors::ParquetError,
+    file::metadata::ParquetMetaData,
+};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A temporary directory holding the input and output of a conversion.
+pub struct Case {
+    pub dir: TempDir,
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+impl Case {
+    /// Writes `csv` to `input.csv` in a new temporary directory.
+    pub fn new(csv: &str) -> Self {
+        Self::with_bytes("input.csv", csv.as_bytes())
+    }
+
+    /// Writes `bytes` to a file named `name` in a new temporary directory.
+    pub fn with_bytes(name: &str, bytes: &[u8]) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join(name);
+        fs::write(&input, bytes).unwrap();
+        let output = dir.path().join("output.parquet");
+        Self { dir, input, output }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    pub fn opts(&self) -> Opts {
+        Opts::new(self.input.clone(), self.output.clone())
+    }
+
+    /// Converts the input with the options changed by `configure`.
+    pub fn convert(&self, configure: impl FnOnce(&mut Opts)) -> Result<(), ParquetError> {
+        let mut opts = self.opts();
+        configure(&mut opts);
+        csv2parquet::convert(opts)
+    }
+
+    /// Reads the output back as a single batch.
+    pub fn read(&self) -> RecordBatch {
+        read_parquet(&self.output)
+    }
+
+    pub fn metadata(&self) -> ParquetMetaData {
+        read_metadata(&self.output)
+    }
+}
+
+pub fn read_parquet(path: &Path) -> RecordBatch {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+    let schema = builder.schema().clone();
+    let batches = builder
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    concat_batches(&schema, &batches).unwrap()
+}
+
+pub fn read_metadata(path: &Path) -> ParquetMetaData {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+    builder.metadata().as_ref().clone()
+}
+
+/// The values of a column as strings, with nulls as `None`.
+pub fn strings(batch: &RecordBatch, column: &str) -> Vec<Option<String>> {
+    let column = batch.column_by_name(column).unwrap();
+    let column = arrow::compute::cast(column, &arrow::datatypes::DataType::Utf8).unwrap();
+    let column = column
+        .as_any()
+        .downcast_ref::<arrow::array::StringArray>()
+        .unwrap();
+    column.iter().map(|value| value.map(String::from)).collect()
+}
+
+/// Expected column values for comparing with [`strings`].
+pub fn opt(values: &[Option<&str>]) -> Vec<Option<String>> {
+    values.iter().map(|value| value.map(String::from)).collect()
+}
+
+/// A reader that returns at most `chunk` bytes per read, like a socket.
+pub struct Chunked<'a> {
+    pub bytes: &'a [u8],
+    pub chunk: usize,
+}
+
+impl std::io::Read for Chunked<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.chunk).min(self.bytes.len());
+        buf[..len].copy_from_slice(&self.bytes[..len]);
+        self.bytes = &self.bytes[len..];
+        Ok(len)
+    }
+}
@@ -0,0 +1,572 @@
+mod common;
+
+use arrow_schema::DataType;
+use common::*;
+
+#[cfg(unix)]
+#[test]
+fn fifo_input() {
+    let case = Case::new("");
+    let fifo = case.path("input.fifo");
+    let status = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let path = fifo.clone();
+    let writer = std::thread::spawn(move || std::fs::write(path, "a,b\n1,x\n2,y\n3,z\n").unwrap());
+    case.convert(|opts| opts.input = fifo).unwrap();
+    writer.join().unwrap();
+
+    let batch = case.read();
+    assert_eq!(batch.num_rows(), 3);
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(
+        strings(&batch, "b"),
+        opt(&[Some("x"), Some("y"), Some("z")])
+    );
+}
+
+fn zip_case(entries: &[(&str, &str)]) -> Case {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in entries {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+    }
+    Case::with_bytes("input.zip", &zip.finish().unwrap().into_inner())
+}
+
+#[test]
+fn zip_input() {
+    let case = zip_case(&[("readme.txt", "not csv"), ("data.csv", "a,b\n1,x\n2,y\n")]);
+    case.convert(|_| {}).unwrap();
+
+    let batch = case.read();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x"), Some("y")]));
+}
+
+#[test]
+fn zip_input_with_several_csv_files() {
+    let case = zip_case(&[("one.csv", "a\n1\n"), ("two.csv", "b\n2\n")]);
+    let error = case.convert(|_| {}).unwrap_err();
+    assert!(error.to_string().contains("several CSV files"));
+
+    case.convert(|opts| opts.zip_entry = Some("two.csv".to_string()))
+        .unwrap();
+    assert_eq!(strings(&case.read(), "b"), opt(&[Some("2")]));
+}
+
+fn read_json(path: &std::path::Path) -> serde_json::Value {
+    serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn max_infer_bytes() {
+    let csv = format!("a\n{}", "12\n".repeat(100));
+    let case = Case::new("");
+    let manifest = case.path("manifest.json");
+    let mut opts = case.opts();
+    opts.max_infer_bytes = Some(32);
+    opts.manifest = Some(manifest.clone());
+    csv2parquet::convert_from_read(csv.as_bytes(), opts).unwrap();
+
+    // The header and ten records fit in 32 bytes.
+    assert_eq!(read_json(&manifest)["inferred_records"], 10);
+    let batch = case.read();
+    assert_eq!(batch.num_rows(), 100);
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+}
+
+#[test]
+fn column_null_values() {
+    let case = Case::new("a,b\n1,NONE\n-1,x\n2,-1\n");
+    case.convert(|opts| {
+        opts.column_null_values = vec![
+            ("a".to_string(), "-1".to_string()),
+            ("b".to_string(), "NONE".to_string()),
+        ]
+    })
+    .unwrap();
+
+    let batch = case.read();
+    assert_eq!(strings(&batch, "a"), opt(&[Some("1"), None, Some("2")]));
+    assert_eq!(strings(&batch, "b"), opt(&[None, Some("x"), Some("-1")]));
+}
+
+#[test]
+fn header_rows() {
+    let case = Case::new("time,temp,note\ns,C,\n1,20.5,a\n2,21.0,b\n");
+    case.convert(|opts| opts.header_rows = Some(2)).unwrap();
+
+    let batch = case.read();
+    let names: Vec<&str> = batch
+        .schema_ref()
+        .fields()
+        .iter()
+        .map(|field| field.name().as_str())
+        .collect();
+    assert_eq!(names, ["time_s", "temp_C", "note"]);
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Float64);
+    assert_eq!(strings(&batch, "note"), opt(&[Some("a"), Some("b")]));
+}
+
+#[test]
+fn detect_delimiter() {
+    let case = Case::new("a;b;c\n1;x,y;2.5\n2;z;3.5\n");
+    case.convert(|opts| opts.detect_delimiter = true).unwrap();
+
+    let batch = case.read();
+    assert_eq!(batch.num_columns(), 3);
+    assert_eq!(batch.schema().field(2).data_type(), &DataType::Float64);
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x,y"), Some("z")]));
+}
+
+/// A CSV with a single column `n` holding the row numbers from zero.
+fn numbers(rows: usize) -> String {
+    std::iter::once("n\n".to_string())
+        .chain((0..rows).map(|row| format!("{row}\n")))
+        .collect()
+}
+
+fn range_strings(range: std::ops::Range<usize>) -> Vec<Option<String>> {
+    range.map(|row| Some(row.to_string())).collect()
+}
+
+#[test]
+fn row_range() {
+    let case = Case::new(&numbers(50));
+    case.convert(|opts| opts.row_range = Some((10, 20)))
+        .unwrap();
+    assert_eq!(strings(&case.read(), "n"), range_strings(10..20));
+
+    // The range spans two batches of the reader.
+    let case = Case::new(&numbers(3000));
+    case.convert(|opts| opts.row_range = Some((1000, 1030)))
+        .unwrap();
+    assert_eq!(strings(&case.read(), "n"), range_strings(1000..1030));
+}
+
+#[test]
+fn convert_from_read() {
+    let csv = numbers(2000);
+    let case = Case::new("");
+    let reader = Chunked {
+        bytes: csv.as_bytes(),
+        chunk: 3,
+    };
+    csv2parquet::convert_from_read(reader, case.opts()).unwrap();
+
+    let batch = case.read();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "n"), range_strings(0..2000));
+}
+
+#[test]
+fn pre_scan_of_streamed_input() {
+    let csv = "a,b,c\n 1,2,x\n+2,3,y\n03,4,z\n";
+    let case = Case::new("");
+    let reader = Chunked {
+        bytes: csv.as_bytes(),
+        chunk: 3,
+    };
+    let mut opts = case.opts();
+    opts.max_read_records = Some(2);
+    opts.type_priority = Some(vec!["float".to_string(), "integer".to_string()]);
+    opts.lenient_integers = true;
+    opts.dictionary = true;
+    // Only the sampled values of a exceed a byte on average.
+    opts.no_dictionary_for_large_columns = Some(1);
+    opts.write_header_map = Some(case.path("header_map.json"));
+    csv2parquet::convert_from_read(reader, opts).unwrap();
+
+    let batch = case.read();
+    let types: Vec<_> = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.data_type().clone())
+        .collect();
+    assert_eq!(types, [DataType::Int64, DataType::Float64, DataType::Utf8]);
+    assert_eq!(
+        strings(&batch, "a"),
+        opt(&[Some("1"), Some("2"), Some("3")])
+    );
+    let dictionaries: Vec<_> = case
+        .metadata()
+        .row_group(0)
+        .columns()
+        .iter()
+        .map(|column| column.dictionary_page_offset().is_some())
+        .collect();
+    assert_eq!(dictionaries, [false, true, true]);
+    assert!(case.path("header_map.json").exists());
+}
+
+#[test]
+fn pre_scan_within_max_infer_bytes() {
+    // Only the first records fit the byte limit, the rest of the stream isn't buffered.
+    let csv: String = std::iter::once("a,b\n".to_string())
+        .chain((0..2000).map(|row| format!("{row},value {row}\n")))
+        .collect();
+    let options: [fn(&mut csv2parquet::Opts); 2] = [
+        |opts| opts.lenient_integers = true,
+        |opts| opts.no_dictionary_for_large_columns = Some(3),
+    ];
+    for configure in options {
+        let case = Case::new("");
+        let reader = Chunked {
+            bytes: csv.as_bytes(),
+            chunk: 10,
+        };
+        let mut opts = case.opts();
+        opts.max_infer_bytes = Some(100);
+        configure(&mut opts);
+        csv2parquet::convert_from_read(reader, opts).unwrap();
+
+        let batch = case.read();
+        assert_eq!(batch.num_rows(), 2000);
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    }
+}
+
+#[test]
+fn read_ahead() {
+    let csv = numbers(2000);
+    for read_ahead in [1, 16, 1 << 20] {
+        let case = Case::new("");
+        let reader = Chunked {
+            bytes: csv.as_bytes(),
+            chunk: 7,
+        };
+        let mut opts = case.opts();
+        opts.read_ahead = Some(read_ahead);
+        opts.max_read_records = Some(10);
+        csv2parquet::convert_from_read(reader, opts).unwrap();
+        assert_eq!(strings(&case.read(), "n"), range_strings(0..2000));
+    }
+}
+
+#[test]
+fn force_streaming() {
+    let case = Case::new(&numbers(2000));
+    for read_ahead in [None, Some(16)] {
+        case.convert(|opts| {
+            opts.force_streaming = true;
+            opts.read_ahead = read_ahead;
+            opts.max_read_records = Some(10);
+        })
+        .unwrap();
+
+        let batch = case.read();
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+        assert_eq!(strings(&batch, "n"), range_strings(0..2000));
+    }
+}
+
+#[test]
+fn strict_line_endings() {
+    let case = Case::new("a,b\r\n1,\"x\ny\"\r\n2,z\n3,w\r\n");
+    case.convert(|_| {}).unwrap();
+    let batch = case.read();
+    assert_eq!(
+        strings(&batch, "a"),
+        opt(&[Some("1"), Some("2"), Some("3")])
+    );
+    assert_eq!(
+        strings(&batch, "b"),
+        opt(&[Some("x\ny"), Some("z"), Some("w")])
+    );
+
+    let error = case
+        .convert(|opts| opts.strict_line_endings = true)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Record 3 ends with \"\\n\" but earlier records end with \"\\r\\n\""));
+
+    // Line breaks inside quoted fields are not record terminators.
+    let case = Case::new("a,b\r\n1,\"x\ny\"\r\n2,z\r\n");
+    case.convert(|opts| opts.strict_line_endings = true)
+        .unwrap();
+    assert_eq!(case.read().num_rows(), 2);
+}
+
+fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+    std::iter::once(0xFEFF)
+        .chain(text.encode_utf16())
+        .flat_map(|unit| match big_endian {
+            true => unit.to_be_bytes(),
+            false => unit.to_le_bytes(),
+        })
+        .collect()
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn utf16_input() {
+    for big_endian in [false, true] {
+        let case = Case::with_bytes("input.csv", &utf16("id,name\n1,café\n2,😀\n", big_endian));
+        case.convert(|_| {}).unwrap();
+
+        let batch = case.read();
+        assert_eq!(batch.schema().field(0).name(), "id");
+        assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+        assert_eq!(strings(&batch, "name"), opt(&[Some("café"), Some("😀")]));
+    }
+}
+
+#[cfg(not(feature = "encoding"))]
+#[test]
+fn utf16_input_requires_the_encoding_feature() {
+    let case = Case::with_bytes("input.csv", &utf16("id,name\n1,café\n", false));
+    let error = case.convert(|_| {}).unwrap_err();
+    assert!(error.to_string().contains("requires the encoding feature"));
+}
+
+#[test]
+fn text_starting_like_bzip2() {
+    let case = Case::new("BZh9,name\n1,ann\n");
+    case.convert(|_| {}).unwrap();
+    assert_eq!(strings(&case.read(), "BZh9"), opt(&[Some("1")]));
+}
+
+#[cfg(feature = "bzip2")]
+#[test]
+fn bzip2_input_detected_from_magic_bytes() {
+    use std::io::Write;
+
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    encoder.write_all(b"id,name\n1,ann\n").unwrap();
+    let case = Case::with_bytes("input.csv", &encoder.finish().unwrap());
+    case.convert(|_| {}).unwrap();
+    assert_eq!(strings(&case.read(), "name"), opt(&[Some("ann")]));
+}
+
+#[test]
+fn preserve_line_numbers() {
+    let lines = |case: &Case| strings(&case.read(), "__line__");
+
+    let case = Case::new("a,b\n1,x\n\n2,y\n\"3\nz\",w\r\n4,v\n");
+    case.convert(|opts| opts.preserve_line_numbers = true)
+        .unwrap();
+    assert_eq!(
+        lines(&case),
+        opt(&[Some("2"), Some("4"), Some("5"), Some("7")])
+    );
+
+    // Padding rewrites the records, the line numbers are still those of the input.
+    let case = Case::new("a,b\n1,x\n\n2\n\n\"3\nz\",w\n");
+    case.convert(|opts| {
+        opts.preserve_line_numbers = true;
+        opts.on_ragged = csv2parquet::RaggedMode::Pad;
+    })
+    .unwrap();
+    assert_eq!(lines(&case), opt(&[Some("2"), Some("4"), Some("6")]));
+    assert_eq!(
+        strings(&case.read(), "b"),
+        opt(&[Some("x"), None, Some("w")])
+    );
+
+    let case = Case::new("a,b\ns,\n\n1,x\n2,y\n");
+    case.convert(|opts| {
+        opts.preserve_line_numbers = true;
+        opts.header_rows = Some(2);
+    })
+    .unwrap();
+    assert_eq!(lines(&case), opt(&[Some("4"), Some("5")]));
+}
+
+#[test]
+fn quoted_empty_strings_of_ragged_rows() {
+    let case = Case::new("a,b,c\nx,\"\",1\ny,v,2\nz,\"\"");
+    case.convert(|opts| {
+        opts.quoted_empty_as_null = false;
+        opts.on_ragged = csv2parquet::RaggedMode::Pad;
+    })
+    .unwrap();
+
+    // Quoted empty fields are empty strings, padded fields are null.
+    let batch = case.read();
+    assert_eq!(strings(&batch, "b"), opt(&[Some(""), Some("v"), Some("")]));
+    assert_eq!(strings(&batch, "c"), opt(&[Some("1"), Some("2"), None]));
+
+    let case = Case::new("a,b\nu,\nx,\"\"\ny,w\nz,\n");
+    case.convert(|opts| {
+        opts.quoted_empty_as_null = false;
+        opts.header_rows = Some(2);
+    })
+    .unwrap();
+    assert_eq!(
+        strings(&case.read(), "b"),
+        opt(&[Some(""), Some("w"), None])
+    );
+}
+
+#[test]
+fn pad_ragged_rows_with_nulls() {
+    for mode in [
+        csv2parquet::RaggedMode::Pad,
+        csv2parquet::RaggedMode::Truncate,
+    ] {
+        let case = Case::new("a,b,c\nx,,u\ny\nz,w\n");
+        case.convert(|opts| {
+            opts.on_ragged = mode;
+            opts.empty_as_null = Some(false);
+        })
+        .unwrap();
+
+        // Empty fields in the input are empty strings, the padding is null.
+        let batch = case.read();
+        assert_eq!(strings(&batch, "b"), opt(&[Some(""), None, Some("w")]));
+        assert_eq!(strings(&batch, "c"), opt(&[Some("u"), None, None]));
+    }
+}
+
+#[test]
+fn escaped_quote_style() {
+    let csv = "a,b\n\"x\"\"y\",1\n\"say \\\"hi\\\"\",2\n\"\",3\n\"p\nq\",4\n";
+    let case = Case::new(csv);
+    case.convert(|opts| {
+        opts.quote_style = csv2parquet::QuoteStyle::Escape;
+        opts.quoted_empty_as_null = false;
+        opts.preserve_line_numbers = true;
+    })
+    .unwrap();
+
+    // The second quote of `""` ends the quoted part and the rest of the field is read as is.
+    let batch = case.read();
+    assert_eq!(
+        strings(&batch, "a"),
+        opt(&[Some("x\"y\""), Some("say \"hi\""), Some(""), Some("p\nq")])
+    );
+    assert_eq!(
+        strings(&batch, "__line__"),
+        opt(&[Some("2"), Some("3"), Some("4"), Some("5")])
+    );
+
+    case.convert(|_| {}).unwrap();
+    assert_eq!(strings(&case.read(), "a")[0], Some("x\"y".to_string()));
+}
+
+#[test]
+fn ignore_trailing_delimiter() {
+    let case = Case::new("a,b,\n1,\"x,\",\n2,y,\n");
+    case.convert(|_| {}).unwrap();
+    assert_eq!(case.read().num_columns(), 3);
+
+    case.convert(|opts| opts.ignore_trailing_delimiter = true)
+        .unwrap();
+    let batch = case.read();
+    assert_eq!(batch.num_columns(), 2);
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x,"), Some("y")]));
+}
+
+#[test]
+fn sniff_dialect() {
+    let case = Case::new("name;score\n\"a;b\";1\nc;2\n");
+    case.convert(|opts| opts.sniff_dialect = true).unwrap();
+    let batch = case.read();
+    assert_eq!(batch.num_columns(), 2);
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "name"), opt(&[Some("a;b"), Some("c")]));
+
+    // Without text in the numeric column, the first line is data.
+    let case = Case::new("\"a;b\";1\n\"c;d\";2\ne;3\n");
+    case.convert(|opts| opts.sniff_dialect = true).unwrap();
+    let batch = case.read();
+    assert_eq!(batch.num_rows(), 3);
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Int64);
+}
+
+#[test]
+fn normalize_newlines() {
+    let case = Case::new("a,b\r1,\"x\ry\"\r2,z\r\n3,w\r");
+    case.convert(|opts| opts.normalize_newlines = true).unwrap();
+    let batch = case.read();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(
+        strings(&batch, "b"),
+        opt(&[Some("x\ry"), Some("z"), Some("w")])
+    );
+}
+
+#[test]
+fn unescape_newlines() {
+    let case = Case::new("id,text\n1,first\\nsecond\n2,a\\tb\n3,C:\\\\new\\x\\\n");
+
+    case.convert(|_| {}).unwrap();
+    assert_eq!(
+        strings(&case.read(), "text"),
+        opt(&[
+            Some("first\\nsecond"),
+            Some("a\\tb"),
+            Some("C:\\\\new\\x\\")
+        ])
+    );
+
+    case.convert(|opts| opts.unescape_newlines = true).unwrap();
+    assert_eq!(
+        strings(&case.read(), "text"),
+        opt(&[Some("first\nsecond"), Some("a\tb"), Some("C:\\new\\x\\")])
+    );
+}
+
+#[test]
+fn column_number_format() {
+    use csv2parquet::NumberFormat;
+
+    let case = Case::new("id,us,eu\n1,\"1,234.5\",\"1.234,5\"\n2,2.5,\"3,25\"\n3,,\n");
+    case.convert(|opts| {
+        opts.column_number_format = vec![
+            ("us".to_string(), NumberFormat::US),
+            ("eu".to_string(), NumberFormat::EU),
+        ]
+    })
+    .unwrap();
+
+    let batch = case.read();
+    for column in ["us", "eu"] {
+        assert_eq!(
+            batch.schema().field_with_name(column).unwrap().data_type(),
+            &DataType::Float64
+        );
+    }
+    assert_eq!(
+        strings(&batch, "us"),
+        opt(&[Some("1234.5"), Some("2.5"), None])
+    );
+    assert_eq!(
+        strings(&batch, "eu"),
+        opt(&[Some("1234.5"), Some("3.25"), None])
+    );
+}
+
+#[test]
+fn nan_as_null() {
+    let case = Case::new("x,y\n1.5,NaN\nNaN,2\n,3\n");
+    let schema = |opts: &mut csv2parquet::Opts| {
+        opts.schema_spec = Some("x:float64,y:float32".to_string());
+    };
+
+    case.convert(schema).unwrap();
+    assert_eq!(
+        strings(&case.read(), "x"),
+        opt(&[Some("1.5"), Some("NaN"), None])
+    );
+
+    case.convert(|opts| {
+        schema(opts);
+        opts.nan_as_null = true;
+    })
+    .unwrap();
+    let batch = case.read();
+    assert_eq!(strings(&batch, "x"), opt(&[Some("1.5"), None, None]));
+    assert_eq!(strings(&batch, "y"), opt(&[None, Some("2.0"), Some("3.0")]));
+    let metadata = case.metadata();
+    let statistics = metadata.row_group(0).column(0).statistics().unwrap();
+    assert_eq!(statistics.null_count_opt(), Some(2));
+}
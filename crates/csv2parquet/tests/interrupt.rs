@@ -0,0 +1,45 @@
+#![cfg(all(unix, feature = "interrupt"))]
+
+mod common;
+
+use common::*;
+
+/// A CSV with more rows than fit in one batch.
+fn csv() -> String {
+    std::iter::once("a\n".to_string())
+        .chain((0..3000).map(|row| format!("{row}\n")))
+        .collect()
+}
+
+/// Converts with `finalize_on_interrupt`, raising SIGINT while the first batch is written.
+fn convert_interrupted(case: &Case) -> Result<(), parquet::errors::ParquetError> {
+    let mut opts = case.opts();
+    opts.finalize_on_interrupt = true;
+    csv2parquet::convert_with_transform(opts, |batch| {
+        if batch.num_rows() > 0 {
+            unsafe {
+                libc::raise(libc::SIGINT);
+            }
+        }
+        Ok(batch)
+    })
+}
+
+#[test]
+fn interrupted_conversions_finish_the_file() {
+    let case = Case::new(&csv());
+
+    for _ in 0..2 {
+        let error = convert_interrupted(&case).unwrap_err();
+        assert!(error.to_string().contains("Interrupted"), "{error}");
+        let rows = case.read().num_rows();
+        assert!(rows > 0 && rows < 3000, "{rows}");
+    }
+
+    // Later conversions are not affected, with or without the option.
+    case.convert(|_| {}).unwrap();
+    assert_eq!(case.read().num_rows(), 3000);
+    case.convert(|opts| opts.finalize_on_interrupt = true)
+        .unwrap();
+    assert_eq!(case.read().num_rows(), 3000);
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::*;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::{Mutex, Once};
+
+/// Collects the messages logged by all tests of this file.
+struct CapturingLogger {
+    messages: Mutex<Vec<(Level, String)>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    messages: Mutex::new(Vec::new()),
+};
+
+fn install_logger() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+/// Whether a message at `level` containing `text` was logged.
+fn logged(level: Level, text: &str) -> bool {
+    LOGGER
+        .messages
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(logged_level, message)| *logged_level == level && message.contains(text))
+}
+
+#[test]
+fn conversion_logs() {
+    install_logger();
+    let case = Case::new("a,b\n1,x\n2,y\n");
+    case.convert(|_| {}).unwrap();
+
+    assert!(logged(Level::Debug, "Inferred schema from 2 records"));
+    assert!(logged(
+        Level::Info,
+        &format!("Wrote 2 rows in 1 row groups to {:?}", case.output)
+    ));
+}
+
+#[test]
+fn log_level() {
+    let mut opts = Case::new("").opts();
+    assert_eq!(opts.log_level(), LevelFilter::Warn);
+    opts.verbose = 2;
+    assert_eq!(opts.log_level(), LevelFilter::Debug);
+}
+
+#[test]
+fn benchmark_report() {
+    install_logger();
+    let case = Case::new("a,b\n1,x\n2,y\n3,z\n");
+    case.convert(|opts| opts.benchmark = true).unwrap();
+
+    assert!(logged(Level::Info, "Benchmark (3 rows):"));
+    let messages = LOGGER.messages.lock().unwrap();
+    for phase in ["read: ", "write: "] {
+        let line = messages
+            .iter()
+            .map(|(_, message)| message)
+            .find(|message| message.starts_with(phase))
+            .unwrap();
+        assert!(line.contains("s, ") && line.contains(" rows/s, ") && line.ends_with(" MB/s"));
+    }
+}
+
+#[test]
+fn encoding_warnings() {
+    install_logger();
+    let case = Case::new("a,b\n1,2\n3,4\n");
+    case.convert(|opts| {
+        opts.dictionary = true;
+        opts.encoding = Some(csv2parquet::ParquetEncoding::DELTA_BINARY_PACKED);
+    })
+    .unwrap();
+    assert!(logged(
+        Level::Warn,
+        "Dictionary encoding is enabled, so the DELTA_BINARY_PACKED encoding is only used"
+    ));
+
+    let mut opts = case.opts();
+    opts.encoding = Some(csv2parquet::ParquetEncoding::BYTE_STREAM_SPLIT);
+    let schema = arrow_schema::Schema::new(vec![
+        arrow_schema::Field::new("price", arrow_schema::DataType::Float64, true),
+        arrow_schema::Field::new("name", arrow_schema::DataType::Utf8, true),
+    ]);
+    assert_eq!(
+        opts.encoding_warnings(&schema),
+        ["The BYTE_STREAM_SPLIT encoding does not suit the types of columns [\"name\"]"]
+    );
+
+    opts.encoding = Some(csv2parquet::ParquetEncoding::PLAIN);
+    opts.dictionary = true;
+    assert!(opts.encoding_warnings(&schema).is_empty());
+}
+
+#[test]
+fn sniffed_dialect_report() {
+    install_logger();
+    let case = Case::new("name;score\n\"a;b\";1\nc;2\n");
+    case.convert(|opts| opts.sniff_dialect = true).unwrap();
+
+    assert!(logged(
+        Level::Info,
+        "Sniffed dialect: delimiter ';', quote '\"', header true"
+    ));
+}
+
+#[test]
+fn schema_drift_warnings() {
+    install_logger();
+    let case = Case::new("id,label\n1,x\n");
+    let baseline = case.path("baseline.json");
+    std::fs::write(
+        &baseline,
+        r#"{"fields":[{"name":"id","data_type":"Utf8","nullable":true,"dict_id":0,"dict_is_ordered":false,"metadata":{}}],"metadata":{}}"#,
+    )
+    .unwrap();
+    case.convert(|opts| opts.baseline_schema = Some(baseline.clone()))
+        .unwrap();
+
+    assert!(logged(
+        Level::Warn,
+        "Schema drift from the baseline: column \"id\" changed from Utf8 to Int64"
+    ));
+    assert!(logged(
+        Level::Warn,
+        "Schema drift from the baseline: column \"label\" was added as Utf8"
+    ));
+}
+
+#[test]
+fn count_only() {
+    install_logger();
+    // The quoted newline is part of a field and doesn't start a row.
+    let case = Case::new("a,b\n1,x\n\"2\nz\",y\n3,z\n4,w\n5,v\n");
+    case.convert(|opts| opts.count_only = true).unwrap();
+    assert!(logged(Level::Debug, "Counted 5 data rows"));
+    assert!(!case.output.exists());
+
+    case.convert(|opts| {
+        opts.count_only = true;
+        opts.row_range = Some((1, 4));
+    })
+    .unwrap();
+    assert!(logged(Level::Debug, "Counted 3 data rows"));
+    assert!(!case.output.exists());
+}
@@ -0,0 +1,859 @@
+mod common;
+
+use common::*;
+use std::fs;
+
+fn read_json(path: &std::path::Path) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn manifest() {
+    let case = Case::new("a,b\n1,x\n2,y\n3,z\n");
+    let manifest = case.path("manifest.json");
+    case.convert(|opts| {
+        opts.manifest = Some(manifest.clone());
+        opts.compression = Some(csv2parquet::ParquetCompression::SNAPPY);
+        opts.max_row_group_size = Some(2);
+    })
+    .unwrap();
+
+    let json = read_json(&manifest);
+    let metadata = case.metadata();
+    assert_eq!(json["num_rows"], metadata.file_metadata().num_rows());
+    assert_eq!(json["num_row_groups"], metadata.num_row_groups());
+    assert_eq!(json["num_row_groups"], 2);
+    assert_eq!(json["file_size"], fs::metadata(&case.output).unwrap().len());
+    assert_eq!(json["compression"], "SNAPPY");
+    assert_eq!(json["files"][0], case.output.to_str().unwrap());
+    assert_eq!(json["inferred_records"], 3);
+    assert_eq!(json["schema"]["fields"][1]["name"], "b");
+}
+
+#[test]
+fn parquet_sink() {
+    use arrow::array::{Int64Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+    let batch = |values: Vec<i64>| {
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))]).unwrap()
+    };
+    let case = Case::new("");
+    let opts = case.opts();
+
+    let file = fs::File::create(&case.output).unwrap();
+    let mut sink =
+        csv2parquet::ParquetSink::try_new_with_opts(file, schema.clone(), &opts).unwrap();
+    sink.write_batch(&batch(vec![1, 2])).unwrap();
+    sink.write_batch(&batch(vec![3])).unwrap();
+    assert_eq!(sink.in_progress_rows(), 3);
+    sink.flush_row_group().unwrap();
+    assert_eq!(sink.flushed_row_groups(), 1);
+    sink.write_batch(&batch(vec![4, 5])).unwrap();
+    let metadata = sink.finish().unwrap();
+    assert_eq!(metadata.num_rows, 5);
+
+    let row_groups: Vec<i64> = case
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows())
+        .collect();
+    assert_eq!(row_groups, [3, 2]);
+    assert_eq!(
+        strings(&case.read(), "a"),
+        opt(&[Some("1"), Some("2"), Some("3"), Some("4"), Some("5")])
+    );
+}
+
+#[test]
+fn verify_after_write() {
+    let case = Case::new("a\n1\n2\n");
+    case.convert(|opts| opts.verify_after_write = true).unwrap();
+    assert_eq!(case.read().num_rows(), 2);
+}
+
+#[test]
+fn estimate_writes_no_output() {
+    let case = Case::new("a\n1\n2\n");
+    case.convert(|opts| opts.estimate = true).unwrap();
+    assert!(!case.output.exists());
+}
+
+/// Whether each column of the first row group has a dictionary page.
+fn dictionary_columns(case: &Case) -> Vec<bool> {
+    case.metadata()
+        .row_group(0)
+        .columns()
+        .iter()
+        .map(|column| column.dictionary_page_offset().is_some())
+        .collect()
+}
+
+#[test]
+fn no_dictionary_columns() {
+    let case = Case::new("a,b\nx,y\nx,y\nx,y\n");
+    case.convert(|opts| {
+        opts.dictionary = true;
+        opts.no_dictionary_columns = vec!["b".to_string()];
+    })
+    .unwrap();
+
+    assert_eq!(dictionary_columns(&case), [true, false]);
+    let encodings = case.metadata().row_group(0).column(1).encodings().to_vec();
+    assert_eq!(
+        encodings,
+        [
+            parquet::basic::Encoding::PLAIN,
+            parquet::basic::Encoding::RLE
+        ]
+    );
+}
+
+#[test]
+fn write_buffer_size() {
+    let csv: String = std::iter::once("a,b\n".to_string())
+        .chain((0..1000).map(|row| format!("{row},value {row}\n")))
+        .collect();
+    let case = Case::new(&csv);
+    case.convert(|_| {}).unwrap();
+    let expected = fs::read(&case.output).unwrap();
+
+    for capacity in [1, 64 * 1024] {
+        case.convert(|opts| opts.write_buffer_size = Some(capacity))
+            .unwrap();
+        assert_eq!(fs::read(&case.output).unwrap(), expected);
+    }
+    assert_eq!(case.read().num_rows(), 1000);
+}
+
+#[test]
+fn temp_dir() {
+    let case = Case::new("a\n1\nx\n");
+    let temp_dir = case.path("tmp");
+    fs::create_dir(&temp_dir).unwrap();
+    let temp_files = || fs::read_dir(&temp_dir).unwrap().count();
+
+    case.convert(|opts| opts.temp_dir = Some(temp_dir.clone()))
+        .unwrap();
+    assert_eq!(strings(&case.read(), "a"), opt(&[Some("1"), Some("x")]));
+    assert_eq!(temp_files(), 0);
+
+    // A failed conversion leaves neither the temporary file nor a new output behind.
+    fs::remove_file(&case.output).unwrap();
+    case.convert(|opts| {
+        opts.temp_dir = Some(temp_dir.clone());
+        opts.max_read_records = Some(1);
+    })
+    .unwrap_err();
+    assert!(!case.output.exists());
+    assert_eq!(temp_files(), 0);
+}
+
+#[test]
+fn success_marker() {
+    let case = Case::new("a\n1\n");
+    case.convert(|opts| opts.success_marker = true).unwrap();
+    assert!(case.path("_SUCCESS").exists());
+
+    // Fails while writing, on the null in the second row.
+    let case = Case::new("a\n1\n\"\"\n");
+    let error = case.convert(|opts| {
+        opts.success_marker = true;
+        opts.non_null_columns = vec!["a".to_string()];
+    });
+    assert!(error.is_err());
+    assert!(!case.path("_SUCCESS").exists());
+}
+
+#[test]
+fn in_memory_output() {
+    let case = Case::new("a,b\n1,x\n2,y\n");
+    case.convert(|_| {}).unwrap();
+    let on_disk = fs::read(&case.output).unwrap();
+
+    let bytes = csv2parquet::convert_to_vec(case.opts()).unwrap();
+    assert_eq!(bytes, on_disk);
+
+    case.convert(|opts| opts.buffer_in_memory = true).unwrap();
+    assert_eq!(fs::read(&case.output).unwrap(), on_disk);
+}
+
+#[test]
+fn column_max_statistics_size() {
+    use parquet::schema::types::ColumnPath;
+
+    // The parquet writer doesn't limit statistics by size, so the setting is only observable in
+    // the writer properties.
+    let case = Case::new("a,b\nx,y\n");
+    let mut opts = case.opts();
+    opts.max_statistics_size = Some(64);
+    opts.column_max_statistics_size = vec![("b".to_string(), 4)];
+    let props = opts.writer_properties();
+    assert_eq!(props.max_statistics_size(&ColumnPath::from("a")), 64);
+    assert_eq!(props.max_statistics_size(&ColumnPath::from("b")), 4);
+
+    let error = case
+        .convert(|opts| opts.column_max_statistics_size = vec![("c".to_string(), 4)])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Unknown column \"c\" in column_max_statistics_size"));
+}
+
+#[test]
+fn nested_column_paths() {
+    use arrow::array::{ArrayRef, RecordBatch, StringArray, StructArray};
+    use arrow_schema::{DataType, Field, Fields, Schema};
+    use std::sync::Arc;
+
+    let address = Fields::from(vec![
+        Field::new("city", DataType::Utf8, false),
+        Field::new("zip", DataType::Utf8, false),
+    ]);
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "addr",
+        DataType::Struct(address.clone()),
+        false,
+    )]));
+    let column = |values: [&str; 2]| Arc::new(StringArray::from(values.to_vec())) as ArrayRef;
+    let addr = StructArray::new(
+        address,
+        vec![column(["Berlin", "Berlin"]), column(["10115", "10115"])],
+        None,
+    );
+    let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(addr)]).unwrap();
+
+    let write = |opts: &csv2parquet::Opts| {
+        let file = fs::File::create(&opts.output)?;
+        let mut sink = csv2parquet::ParquetSink::try_new_with_opts(file, schema.clone(), opts)?;
+        sink.write_batch(&batch)?;
+        sink.finish()
+    };
+
+    let case = Case::new("");
+    let mut opts = case.opts();
+    opts.dictionary = true;
+    opts.no_dictionary_columns = vec!["addr.city".to_string()];
+    write(&opts).unwrap();
+    assert_eq!(dictionary_columns(&case), [false, true]);
+
+    opts.column_path_separator = Some('/');
+    opts.no_dictionary_columns = vec!["addr/zip".to_string()];
+    write(&opts).unwrap();
+    assert_eq!(dictionary_columns(&case), [true, false]);
+
+    for column in ["addr", "addr.zip", "addr/street"] {
+        opts.no_dictionary_columns = vec![column.to_string()];
+        let error = write(&opts).err().unwrap();
+        assert!(error.to_string().contains(&format!(
+            "Unknown column {column:?} in no_dictionary_columns"
+        )));
+    }
+
+    // Top-level columns whose names contain the separator need another separator.
+    let case = Case::new("a.b,c\nx,y\n");
+    let error = case
+        .convert(|opts| opts.column_max_statistics_size = vec![("a.b".to_string(), 4)])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Unknown column \"a.b\" in column_max_statistics_size"));
+    case.convert(|opts| {
+        opts.column_path_separator = None;
+        opts.column_max_statistics_size = vec![("a.b".to_string(), 4)];
+    })
+    .unwrap();
+}
+
+#[test]
+fn first_column_is_key() {
+    let case = Case::new("id,name\n1,x\n2,y\n3,z\n");
+    case.convert(|opts| opts.first_column_is_key = true)
+        .unwrap();
+
+    let metadata = case.metadata();
+    let row_group = metadata.row_group(0);
+    let sorting = row_group.sorting_columns().unwrap();
+    assert_eq!(sorting.len(), 1);
+    assert_eq!(sorting[0].column_idx, 0);
+    assert!(!sorting[0].descending);
+    assert!(row_group.column(0).bloom_filter_offset().is_some());
+    assert!(row_group.column(1).bloom_filter_offset().is_none());
+}
+
+#[test]
+fn verify_sorted() {
+    let sorted = |opts: &mut csv2parquet::Opts| {
+        opts.first_column_is_key = true;
+        opts.verify_sorted = true;
+    };
+
+    let case = Case::new("id\n1\n2\n2\n5\n");
+    case.convert(sorted).unwrap();
+    assert_eq!(case.read().num_rows(), 4);
+
+    let case = Case::new("id\n1\n3\n2\n5\n");
+    let error = case.convert(sorted).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Column \"id\" is declared sorted but is out of order at row 2"));
+
+    // Rows out of order across a batch boundary.
+    let csv: String = std::iter::once("id\n".to_string())
+        .chain((0..1024).map(|row| format!("{row}\n")))
+        .chain(std::iter::once("0\n".to_string()))
+        .collect();
+    let error = Case::new(&csv).convert(sorted).unwrap_err();
+    assert!(error.to_string().contains("out of order at row 1024"));
+}
+
+#[test]
+fn manifest_inferred_records() {
+    let case = Case::new("a\n1\n2\n3\n4\n5\n");
+    let manifest = case.path("manifest.json");
+    case.convert(|opts| opts.manifest = Some(manifest.clone()))
+        .unwrap();
+    assert_eq!(read_json(&manifest)["inferred_records"], 5);
+
+    case.convert(|opts| {
+        opts.manifest = Some(manifest.clone());
+        opts.max_read_records = Some(2);
+    })
+    .unwrap();
+    assert_eq!(read_json(&manifest)["inferred_records"], 2);
+
+    case.convert(|opts| {
+        opts.manifest = Some(manifest.clone());
+        opts.schema_spec = Some("a:int64".to_string());
+    })
+    .unwrap();
+    assert!(read_json(&manifest)["inferred_records"].is_null());
+}
+
+/// Recomputes the hash from the bytes before the footer metadata of `path`.
+fn content_hash_of(path: &std::path::Path) -> String {
+    use std::hash::Hasher;
+
+    let bytes = fs::read(path).unwrap();
+    let footer = bytes.len() - 8;
+    let footer_len = u32::from_le_bytes(bytes[footer..footer + 4].try_into().unwrap()) as usize;
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    hasher.write(&bytes[..footer - footer_len]);
+    format!("xxh64:{:016x}", hasher.finish())
+}
+
+fn stored_content_hash(case: &Case) -> String {
+    let metadata = case.metadata();
+    let key_values = metadata.file_metadata().key_value_metadata().unwrap();
+    let entry = key_values
+        .iter()
+        .find(|entry| entry.key == "content_hash")
+        .unwrap();
+    entry.value.clone().unwrap()
+}
+
+#[test]
+fn content_hash() {
+    let csv = "a,b\n1,x\n2,\n3,z\n";
+    let case = Case::new(csv);
+    case.convert(|opts| {
+        opts.content_hash = true;
+        opts.max_row_group_size = Some(2);
+    })
+    .unwrap();
+    let hash = stored_content_hash(&case);
+    assert_eq!(hash, content_hash_of(&case.output));
+    assert_eq!(
+        strings(&case.read(), "b"),
+        opt(&[Some("x"), None, Some("z")])
+    );
+
+    let other = Case::new(csv);
+    other
+        .convert(|opts| {
+            opts.content_hash = true;
+            opts.max_row_group_size = Some(2);
+        })
+        .unwrap();
+    assert_eq!(stored_content_hash(&other), hash);
+
+    let with_null_columns = Case::new("a,b\n1,\n2,\n");
+    with_null_columns
+        .convert(|opts| {
+            opts.content_hash = true;
+            opts.omit_stats_for_null_columns = true;
+        })
+        .unwrap();
+    assert_eq!(
+        stored_content_hash(&with_null_columns),
+        content_hash_of(&with_null_columns.output)
+    );
+    let metadata = with_null_columns.metadata();
+    assert!(metadata.row_group(0).column(1).statistics().is_none());
+}
+
+#[test]
+fn geometry_columns() {
+    // POINT (1 2) as hex-encoded WKB.
+    let case = Case::new("id,geom\n1,0101000000000000000000F03F0000000000000040\n2,\n");
+    case.convert(|opts| opts.geometry_columns = vec!["geom".to_string()])
+        .unwrap();
+
+    let batch = case.read();
+    assert_eq!(
+        batch.schema().field(1).data_type(),
+        &arrow::datatypes::DataType::Binary
+    );
+    let geom = batch.column(1).as_any();
+    let geom = geom.downcast_ref::<arrow::array::BinaryArray>().unwrap();
+    assert_eq!(geom.value(0).len(), 21);
+    assert!(arrow::array::Array::is_null(geom, 1));
+
+    let metadata = case.metadata();
+    let key_values = metadata.file_metadata().key_value_metadata().unwrap();
+    let geo = key_values.iter().find(|entry| entry.key == "geo").unwrap();
+    let geo: serde_json::Value = serde_json::from_str(geo.value.as_deref().unwrap()).unwrap();
+    assert_eq!(geo["version"], "1.1.0");
+    assert_eq!(geo["primary_column"], "geom");
+    assert_eq!(geo["columns"]["geom"]["encoding"], "WKB");
+    assert!(geo["columns"].get("id").is_none());
+
+    let error = case
+        .convert(|opts| opts.geometry_columns = vec!["shape".to_string()])
+        .unwrap_err();
+    assert!(error.to_string().contains("shape"));
+
+    let error = case
+        .convert(|opts| {
+            opts.geometry_columns = vec!["geom".to_string()];
+            opts.metadata = vec![("geo".to_string(), "{}".to_string())];
+        })
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("The geo metadata key is written for geometry_columns"));
+}
+
+#[test]
+fn input_dir() {
+    let case = Case::new("");
+    let input_dir = case.path("tables");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(input_dir.join("users.csv"), "id,name\n1,ann\n2,bob\n").unwrap();
+    fs::write(input_dir.join("orders.CSV"), "id,total\n7,1.5\n").unwrap();
+    fs::write(input_dir.join("notes.txt"), "not a table\n").unwrap();
+
+    let output_dir = case.path("dataset");
+    case.convert(|opts| {
+        opts.input_dir = Some(input_dir.clone());
+        opts.output = output_dir.clone();
+    })
+    .unwrap();
+
+    let mut names: Vec<_> = fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["orders.parquet", "users.parquet"]);
+
+    let users = read_parquet(&output_dir.join("users.parquet"));
+    assert_eq!(strings(&users, "name"), opt(&[Some("ann"), Some("bob")]));
+    let orders = read_parquet(&output_dir.join("orders.parquet"));
+    assert_eq!(strings(&orders, "total"), opt(&[Some("1.5")]));
+}
+
+#[test]
+fn input_dir_without_inputs() {
+    let case = Case::new("");
+    let input_dir = case.path("tables");
+    fs::create_dir(&input_dir).unwrap();
+    let convert = |skip_empty_files| {
+        case.convert(|opts| {
+            opts.input_dir = Some(input_dir.clone());
+            opts.output = case.path("dataset");
+            opts.skip_empty_files = skip_empty_files;
+        })
+        .unwrap_err()
+        .to_string()
+    };
+
+    fs::write(input_dir.join("notes.txt"), "not a table\n").unwrap();
+    for skip_empty_files in [true, false] {
+        assert!(convert(skip_empty_files).contains("No CSV files in the input directory"));
+    }
+
+    fs::write(input_dir.join("empty.csv"), "").unwrap();
+    assert!(convert(true).contains("Every CSV file in the input directory is empty"));
+}
+
+#[test]
+fn input_dir_events() {
+    let case = Case::new("");
+    let input_dir = case.path("tables");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(input_dir.join("orders.csv"), "id,total\n7,1.5\n").unwrap();
+    fs::write(input_dir.join("users.csv"), "id,name\n1,ann\n2,bob\n").unwrap();
+
+    case.convert(|opts| {
+        opts.input_dir = Some(input_dir.clone());
+        opts.output = case.path("dataset");
+        opts.events = Some(case.path("events.jsonl"));
+    })
+    .unwrap();
+
+    let events = fs::read_to_string(case.path("events.jsonl")).unwrap();
+    let done: Vec<_> = events
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event["event"] == "done")
+        .map(|event| event["rows"].as_u64().unwrap())
+        .collect();
+    assert_eq!(done, [1, 2]);
+}
+
+#[test]
+fn input_dir_header_map() {
+    let case = Case::new("");
+    let input_dir = case.path("tables");
+    fs::create_dir(&input_dir).unwrap();
+    fs::write(input_dir.join("orders.csv"), "id,total\n7,1.5\n").unwrap();
+    fs::write(input_dir.join("users.csv"), "id,name\n1,ann\n").unwrap();
+
+    for name in ["header_map.csv", "header_map.json"] {
+        case.convert(|opts| {
+            opts.input_dir = Some(input_dir.clone());
+            opts.output = case.path("dataset");
+            opts.write_header_map = Some(case.path(name));
+        })
+        .unwrap();
+    }
+
+    assert_eq!(
+        fs::read_to_string(case.path("header_map.csv")).unwrap(),
+        "file,source,column\n\
+         orders.csv,id,id\norders.csv,total,total\n\
+         users.csv,id,id\nusers.csv,name,name\n"
+    );
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(case.path("header_map.json")).unwrap()).unwrap();
+    let files: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| format!("{}:{}", entry["file"], entry["column"]))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            r#""orders.csv":"id""#,
+            r#""orders.csv":"total""#,
+            r#""users.csv":"id""#,
+            r#""users.csv":"name""#
+        ]
+    );
+}
+
+#[test]
+fn skip_arrow_schema_metadata() {
+    let has_arrow_schema = |case: &Case| {
+        let metadata = case.metadata();
+        let key_values = metadata.file_metadata().key_value_metadata();
+        key_values
+            .is_some_and(|key_values| key_values.iter().any(|entry| entry.key == "ARROW:schema"))
+    };
+
+    let case = Case::new("a,b\n1,x\n");
+    case.convert(|_| {}).unwrap();
+    assert!(has_arrow_schema(&case));
+
+    case.convert(|opts| opts.skip_arrow_schema_metadata = true)
+        .unwrap();
+    assert!(!has_arrow_schema(&case));
+    assert_eq!(strings(&case.read(), "b"), opt(&[Some("x")]));
+}
+
+fn row_group_sizes(case: &Case) -> Vec<i64> {
+    case.metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows())
+        .collect()
+}
+
+#[test]
+fn target_row_groups() {
+    let csv: String = std::iter::once("n\n".to_string())
+        .chain((0..10).map(|row| format!("{row}\n")))
+        .collect();
+    let case = Case::new(&csv);
+    case.convert(|opts| opts.target_row_groups = Some(3))
+        .unwrap();
+    assert_eq!(row_group_sizes(&case), [4, 4, 2]);
+
+    // Piped input cannot be counted ahead, so the output is rewritten into the row groups.
+    let reader = Chunked {
+        bytes: csv.as_bytes(),
+        chunk: 3,
+    };
+    let mut opts = case.opts();
+    opts.target_row_groups = Some(3);
+    opts.max_read_records = Some(2);
+    opts.content_hash = true;
+    csv2parquet::convert_from_read(reader, opts).unwrap();
+    assert_eq!(row_group_sizes(&case), [4, 4, 2]);
+    assert_eq!(strings(&case.read(), "n").len(), 10);
+    assert_eq!(stored_content_hash(&case), content_hash_of(&case.output));
+
+    let mut opts = case.opts();
+    opts.target_row_groups = Some(3);
+    opts.force_streaming = true;
+    let error = csv2parquet::convert_to_vec(opts).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("target_row_groups needs an output file"));
+}
+
+#[test]
+fn max_output_bytes() {
+    let csv: String = std::iter::once("n,name\n".to_string())
+        .chain((0..1000).map(|row| format!("{row},name {row}\n")))
+        .collect();
+    let case = Case::new(&csv);
+    case.convert(|opts| opts.max_row_group_size = Some(100))
+        .unwrap();
+    let limit = fs::metadata(&case.output).unwrap().len() as usize / 2;
+
+    case.convert(|opts| {
+        opts.max_row_group_size = Some(100);
+        opts.max_output_bytes = Some(limit);
+        opts.manifest = Some(case.path("manifest.json"));
+    })
+    .unwrap();
+    assert!(fs::metadata(&case.output).unwrap().len() as usize <= limit);
+    let sizes = row_group_sizes(&case);
+    assert!(!sizes.is_empty() && sizes.len() < 10);
+    assert!(sizes.iter().all(|&rows| rows == 100));
+    assert_eq!(read_json(&case.path("manifest.json"))["truncated"], true);
+}
+
+#[test]
+fn created_by_tool() {
+    let case = Case::new("a\n1\n");
+    let created_by = |case: &Case| {
+        let metadata = case.metadata();
+        metadata.file_metadata().created_by().unwrap().to_string()
+    };
+    let tool = Some(("ingest".to_string(), "2.1.0".to_string()));
+
+    case.convert(|opts| opts.created_by_tool = tool.clone())
+        .unwrap();
+    assert_eq!(
+        created_by(&case),
+        format!(
+            "ingest version 2.1.0 (csv2parquet version {})",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+
+    case.convert(|opts| {
+        opts.created_by_tool = tool.clone();
+        opts.created_by = Some("custom".to_string());
+    })
+    .unwrap();
+    assert_eq!(created_by(&case), "custom");
+}
+
+#[test]
+fn metadata_file() {
+    let case = Case::new("a\n1\n");
+    let metadata_file = case.path("metadata.json");
+    fs::write(&metadata_file, r#"{"owner": "data", "source": "file"}"#).unwrap();
+    case.convert(|opts| {
+        opts.metadata_file = Some(metadata_file.clone());
+        opts.metadata = vec![("source".to_string(), "inline".to_string())];
+    })
+    .unwrap();
+
+    let metadata = case.metadata();
+    let key_values = metadata.file_metadata().key_value_metadata().unwrap();
+    let value = |key: &str| {
+        key_values
+            .iter()
+            .find(|entry| entry.key == key)
+            .and_then(|entry| entry.value.clone())
+    };
+    assert_eq!(value("owner").as_deref(), Some("data"));
+    assert_eq!(value("source").as_deref(), Some("inline"));
+    assert_eq!(
+        key_values
+            .iter()
+            .filter(|entry| entry.key == "source")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn row_group_memory_budget() {
+    let csv: String = std::iter::once("n\n".to_string())
+        .chain((0..1000).map(|row| format!("{row}\n")))
+        .collect();
+    let case = Case::new(&csv);
+
+    case.convert(|_| {}).unwrap();
+    assert_eq!(row_group_sizes(&case), [1000]);
+
+    // A non-null Int64 column takes about 8 bytes per row.
+    case.convert(|opts| opts.row_group_memory_budget = Some(800))
+        .unwrap();
+    let sizes = row_group_sizes(&case);
+    assert!(sizes.len() > 1, "{sizes:?}");
+    assert_eq!(sizes.iter().sum::<i64>(), 1000);
+    assert!(
+        sizes
+            .iter()
+            .all(|&rows| (50..=100).contains(&rows) || rows == *sizes.last().unwrap()),
+        "{sizes:?}"
+    );
+
+    let error = case
+        .convert(|opts| {
+            opts.row_group_memory_budget = Some(800);
+            opts.single_row_group = true;
+        })
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("single_row_group cannot be combined"));
+}
+
+#[test]
+fn single_row_group() {
+    // More rows than a reader batch, so that several batches go into the one row group.
+    let csv: String = std::iter::once("n\n".to_string())
+        .chain((0..3000).map(|row| format!("{row}\n")))
+        .collect();
+    let case = Case::new(&csv);
+
+    case.convert(|opts| opts.single_row_group = true).unwrap();
+    assert_eq!(row_group_sizes(&case), [3000]);
+    assert_eq!(case.read().num_rows(), 3000);
+
+    let error = case
+        .convert(|opts| {
+            opts.single_row_group = true;
+            opts.max_row_group_size = Some(100);
+        })
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("single_row_group cannot be combined"));
+}
+
+#[cfg(feature = "object_store")]
+#[test]
+fn object_store_output() {
+    use object_store::{memory::InMemory, path::Path, ObjectStore};
+    use std::sync::Arc;
+
+    let case = Case::new("a,b\n1,x\n2,y\n");
+    let store = Arc::new(InMemory::new());
+    let location = Path::from("data/output.parquet");
+    csv2parquet::convert_to_object_store(case.opts(), store.clone(), location.clone()).unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let bytes = runtime
+        .block_on(async { store.get(&location).await?.bytes().await })
+        .unwrap();
+    let output = case.path("downloaded.parquet");
+    fs::write(&output, &bytes).unwrap();
+    let batch = read_parquet(&output);
+    assert_eq!(strings(&batch, "a"), opt(&[Some("1"), Some("2")]));
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x"), Some("y")]));
+
+    // A failed conversion aborts the upload and leaves nothing behind.
+    let failed = Path::from("data/failed.parquet");
+    let case = Case::new("a\n1\nx\n");
+    let mut opts = case.opts();
+    opts.max_read_records = Some(1);
+    csv2parquet::convert_to_object_store(opts, store.clone(), failed.clone()).unwrap_err();
+    assert!(runtime.block_on(store.head(&failed)).is_err());
+}
+
+#[test]
+fn omit_stats_for_null_columns() {
+    let case = Case::new("a,b,c\n1,,x\n2,,y\n");
+    let has_statistics = |case: &Case| {
+        let metadata = case.metadata();
+        metadata
+            .row_group(0)
+            .columns()
+            .iter()
+            .map(|column| column.statistics().is_some())
+            .collect::<Vec<_>>()
+    };
+
+    case.convert(|_| {}).unwrap();
+    assert_eq!(has_statistics(&case), [true, true, true]);
+
+    case.convert(|opts| opts.omit_stats_for_null_columns = true)
+        .unwrap();
+    assert_eq!(has_statistics(&case), [true, false, true]);
+    assert_eq!(strings(&case.read(), "b"), opt(&[None, None]));
+}
+
+#[test]
+fn convert_with_transform() {
+    use arrow::array::{Int64Array, RecordBatch};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let case = Case::new("a\n1\n2\n3\n");
+    let mut calls = 0;
+    csv2parquet::convert_with_transform(case.opts(), |batch| {
+        calls += 1;
+        let a = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let doubled: Int64Array = a.iter().map(|value| value.map(|value| value * 2)).collect();
+        let mut fields = batch.schema().fields().to_vec();
+        fields.push(Arc::new(Field::new("doubled", DataType::Int64, true)));
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(doubled));
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    })
+    .unwrap();
+    // Once with the empty batch for the schema, then with the rows.
+    assert_eq!(calls, 2);
+
+    let batch = case.read();
+    assert_eq!(
+        strings(&batch, "doubled"),
+        opt(&[Some("2"), Some("4"), Some("6")])
+    );
+}
+
+#[test]
+fn no_dictionary_for_large_columns() {
+    let text = "a long text value that repeats".repeat(4);
+    let csv: String = std::iter::once("code,text\n".to_string())
+        .chain((0..10).map(|row| format!("c{},{text} {row}\n", row % 2)))
+        .collect();
+    let case = Case::new(&csv);
+
+    case.convert(|opts| opts.dictionary = true).unwrap();
+    assert_eq!(dictionary_columns(&case), [true, true]);
+
+    case.convert(|opts| {
+        opts.dictionary = true;
+        opts.no_dictionary_for_large_columns = Some(16);
+    })
+    .unwrap();
+    assert_eq!(dictionary_columns(&case), [true, false]);
+}
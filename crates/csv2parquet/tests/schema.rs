@@ -0,0 +1,660 @@
+mod common;
+
+use arrow_schema::DataType;
+use common::*;
+
+#[test]
+fn schema_spec() {
+    let case = Case::new("id,price\n1,1.50\n2,2.25\n");
+    case.convert(|opts| opts.schema_spec = Some("id:int32!,price:decimal(10,2)".to_string()))
+        .unwrap();
+
+    let batch = case.read();
+    let schema = batch.schema();
+    assert_eq!(schema.field(0).data_type(), &DataType::Int32);
+    assert!(!schema.field(0).is_nullable());
+    assert_eq!(schema.field(1).data_type(), &DataType::Decimal128(10, 2));
+    assert_eq!(strings(&batch, "price"), opt(&[Some("1.50"), Some("2.25")]));
+}
+
+#[test]
+fn schema_spec_malformed() {
+    let case = Case::new("id\n1\n");
+    let error = case
+        .convert(|opts| opts.schema_spec = Some("id:int64,name".to_string()))
+        .unwrap_err();
+    assert!(error.to_string().contains("Invalid column spec 'name'"));
+}
+
+#[test]
+fn max_read_records_zero_reads_strings() {
+    let case = Case::new("id,price,day\n1,1.5,2024-01-15\n2,2.5,2024-01-16\n");
+    case.convert(|opts| opts.max_read_records = Some(0))
+        .unwrap();
+
+    let batch = case.read();
+    assert!(batch
+        .schema()
+        .fields()
+        .iter()
+        .all(|field| field.data_type() == &DataType::Utf8));
+    assert_eq!(strings(&batch, "price"), opt(&[Some("1.5"), Some("2.5")]));
+}
+
+fn column_names(batch: &arrow::array::RecordBatch) -> Vec<String> {
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+#[test]
+fn reorder_to_schema() {
+    let case = Case::new("b,a\nx,1\ny,2\n");
+    case.convert(|opts| {
+        opts.schema_spec = Some("a:int64,b:string".to_string());
+        opts.reorder_to_schema = true;
+    })
+    .unwrap();
+
+    let batch = case.read();
+    assert_eq!(column_names(&batch), ["a", "b"]);
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "a"), opt(&[Some("1"), Some("2")]));
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x"), Some("y")]));
+}
+
+#[test]
+fn lenient_integers() {
+    let case = Case::new("a\n007\n+42\n 5 \n-3\n");
+    case.convert(|_| {}).unwrap();
+    assert_eq!(case.read().schema().field(0).data_type(), &DataType::Utf8);
+
+    case.convert(|opts| opts.lenient_integers = true).unwrap();
+    let batch = case.read();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Int64);
+    assert_eq!(
+        strings(&batch, "a"),
+        opt(&[Some("7"), Some("42"), Some("5"), Some("-3")])
+    );
+}
+
+#[test]
+fn timestamp_unit() {
+    let case = Case::new("ts\n2024-01-15T10:00:00.123456\n2024-01-15T11:30:00.000999\n");
+    case.convert(|opts| opts.timestamp_unit = Some(arrow_schema::TimeUnit::Millisecond))
+        .unwrap();
+
+    let batch = case.read();
+    assert_eq!(
+        batch.schema().field(0).data_type(),
+        &DataType::Timestamp(arrow_schema::TimeUnit::Millisecond, None)
+    );
+    assert_eq!(
+        strings(&batch, "ts"),
+        opt(&[Some("2024-01-15T10:00:00.123"), Some("2024-01-15T11:30:00")])
+    );
+}
+
+#[test]
+fn non_null_columns() {
+    let case = Case::new("a,b\n1,x\n2,y\n");
+    case.convert(|opts| opts.non_null_columns = vec!["a".to_string()])
+        .unwrap();
+    let schema = case.read().schema();
+    assert!(!schema.field(0).is_nullable());
+    assert!(schema.field(1).is_nullable());
+
+    let case = Case::new("a,b\n1,x\n,y\n");
+    let error = case
+        .convert(|opts| opts.non_null_columns = vec!["a".to_string()])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Column \"a\" is declared non-null but contains 1 null values"));
+}
+
+#[test]
+fn string_columns() {
+    let case = Case::new("phone,n\n0123,1\n0456,2\n");
+    case.convert(|opts| opts.string_columns = vec!["phone".to_string()])
+        .unwrap();
+
+    let batch = case.read();
+    assert_eq!(batch.schema().field(0).data_type(), &DataType::Utf8);
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Int64);
+    assert_eq!(strings(&batch, "phone"), opt(&[Some("0123"), Some("0456")]));
+}
+
+#[test]
+fn field_metadata() {
+    let case = Case::new("temp,city\n20.5,Berlin\n");
+    case.convert(|opts| {
+        opts.field_metadata = vec![
+            ("temp".to_string(), "unit".to_string(), "C".to_string()),
+            (
+                "temp".to_string(),
+                "source".to_string(),
+                "sensor".to_string(),
+            ),
+        ]
+    })
+    .unwrap();
+
+    let schema = case.read().schema();
+    let metadata = schema.field(0).metadata();
+    assert_eq!(metadata.get("unit").map(String::as_str), Some("C"));
+    assert_eq!(metadata.get("source").map(String::as_str), Some("sensor"));
+    assert!(schema.field(1).metadata().is_empty());
+}
+
+#[test]
+fn column_descriptions() {
+    let case = Case::new("temp,city\n20.5,Berlin\n");
+    case.convert(|opts| {
+        opts.column_descriptions = vec![("city".to_string(), "Nearest city".to_string())];
+        opts.field_metadata = vec![("temp".to_string(), "unit".to_string(), "C".to_string())];
+    })
+    .unwrap();
+
+    let schema = case.read().schema();
+    assert!(schema.field(0).metadata().get("description").is_none());
+    assert_eq!(
+        schema
+            .field(1)
+            .metadata()
+            .get("description")
+            .map(String::as_str),
+        Some("Nearest city")
+    );
+
+    let error = case
+        .convert(|opts| {
+            opts.column_descriptions = vec![("town".to_string(), "Nearest town".to_string())]
+        })
+        .unwrap_err();
+    assert!(error.to_string().contains("town"));
+}
+
+#[test]
+fn duplicate_header_names() {
+    let case = Case::new("id,name,id,id\n1,x,2,3\n");
+    let error = case.convert(|_| {}).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Duplicate column name \"id\" in the CSV header"));
+
+    case.convert(|opts| opts.dedup_header_names = true).unwrap();
+    let batch = case.read();
+    assert_eq!(column_names(&batch), ["id", "name", "id_1", "id_2"]);
+    assert_eq!(strings(&batch, "id_2"), opt(&[Some("3")]));
+}
+
+#[test]
+fn extract_schema() {
+    let case = Case::new("a,b\n1,x\n2,y\n");
+    let schema_file = case.path("schema.json");
+    case.convert(|opts| opts.extract_schema = Some(schema_file.clone()))
+        .unwrap();
+    assert!(!case.output.exists());
+
+    let schema = csv2parquet::validate_schema_file(&schema_file).unwrap();
+    assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+    case.convert(|opts| opts.schema = Some(schema.clone()))
+        .unwrap();
+    assert_eq!(case.read().schema().fields(), schema.fields());
+}
+
+#[test]
+fn large_int_as_decimal() {
+    let case = Case::new("id\n12345678901234567890\n-98765432109876543210\n7\n");
+    case.convert(|_| {}).unwrap();
+    assert_eq!(case.read().schema().field(0).data_type(), &DataType::Utf8);
+
+    case.convert(|opts| opts.large_int_as_decimal = true)
+        .unwrap();
+    let batch = case.read();
+    assert_eq!(
+        batch.schema().field(0).data_type(),
+        &DataType::Decimal128(38, 0)
+    );
+    assert_eq!(
+        strings(&batch, "id"),
+        opt(&[
+            Some("12345678901234567890"),
+            Some("-98765432109876543210"),
+            Some("7")
+        ])
+    );
+}
+
+#[test]
+fn output_column_order() {
+    let case = Case::new("a,b,c\n1,x,2.5\n");
+    case.convert(|opts| {
+        opts.output_column_order = vec!["c".to_string(), "a".to_string(), "b".to_string()]
+    })
+    .unwrap();
+    let batch = case.read();
+    assert_eq!(column_names(&batch), ["c", "a", "b"]);
+    assert_eq!(strings(&batch, "c"), opt(&[Some("2.5")]));
+
+    let error = case
+        .convert(|opts| opts.output_column_order = vec!["c".to_string(), "a".to_string()])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("output_column_order must list every column exactly once"));
+}
+
+#[test]
+fn decimal_rounding() {
+    let case = Case::new("price\n1.005\n2.675\n-0.125\n");
+    let convert = |mode| {
+        case.convert(|opts| {
+            opts.schema_spec = Some("price:decimal(10,2)".to_string());
+            opts.decimal_rounding = Some(mode);
+        })
+        .unwrap();
+        strings(&case.read(), "price")
+    };
+
+    assert_eq!(
+        convert(csv2parquet::RoundingMode::HalfUp),
+        opt(&[Some("1.01"), Some("2.68"), Some("-0.13")])
+    );
+    assert_eq!(
+        convert(csv2parquet::RoundingMode::HalfEven),
+        opt(&[Some("1.00"), Some("2.68"), Some("-0.12")])
+    );
+    assert_eq!(
+        convert(csv2parquet::RoundingMode::Truncate),
+        opt(&[Some("1.00"), Some("2.67"), Some("-0.12")])
+    );
+}
+
+fn data_types(case: &Case) -> Vec<DataType> {
+    let schema = case.read().schema();
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.data_type().clone())
+        .collect()
+}
+
+#[test]
+fn infer_only_columns() {
+    let case = Case::new("id,price,when,flag,note\n1,\"1,5\",2024-01-02,true,\"a \"\"b\"\"\"\n2,,2024-01-03,false,c\n3,2.5,,true,\n");
+    case.convert(|opts| opts.infer_only_columns = vec!["id".to_string(), "when".to_string()])
+        .unwrap();
+    assert_eq!(
+        data_types(&case),
+        [
+            DataType::Int64,
+            DataType::Utf8,
+            DataType::Date32,
+            DataType::Utf8,
+            DataType::Utf8
+        ]
+    );
+    let batch = case.read();
+    assert_eq!(
+        strings(&batch, "note"),
+        opt(&[Some("a \"b\""), Some("c"), None])
+    );
+    assert_eq!(
+        strings(&batch, "when"),
+        opt(&[Some("2024-01-02"), Some("2024-01-03"), None])
+    );
+
+    // The sample only covers the first two records, where the listed price is an integer.
+    let case = Case::new("id,price\n1,1\n2,2\n3,2.5\n");
+    let error = case
+        .convert(|opts| {
+            opts.infer_only_columns = vec!["price".to_string()];
+            opts.max_read_records = Some(2);
+        })
+        .unwrap_err();
+    assert!(error.to_string().contains("2.5"));
+
+    let case = Case::new("1, 007,x\n2,+8,y\n");
+    case.convert(|opts| {
+        opts.header = Some(false);
+        opts.lenient_integers = true;
+        opts.infer_only_columns = vec!["column_2".to_string()];
+    })
+    .unwrap();
+    assert_eq!(
+        data_types(&case),
+        [DataType::Utf8, DataType::Int64, DataType::Utf8]
+    );
+
+    let error = case
+        .convert(|opts| {
+            opts.header = Some(false);
+            opts.infer_only_columns = vec!["column_4".to_string()];
+        })
+        .unwrap_err();
+    assert!(error.to_string().contains("column_4"));
+}
+
+#[test]
+fn validate_schema_file() {
+    let case = Case::new("");
+    let path = case.path("schema.json");
+
+    std::fs::write(
+        &path,
+        r#"{"fields": [{"name": "id", "data_type": "Int64", "nullable": false, "dict_id": 0, "dict_is_ordered": false, "metadata": {}}], "metadata": {}}"#,
+    )
+    .unwrap();
+    let schema = csv2parquet::validate_schema_file(&path).unwrap();
+    assert_eq!(schema.field(0).name(), "id");
+    assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+
+    std::fs::write(&path, "{\"fields\": [\n  {\"name\": \"id\",}\n]}").unwrap();
+    let error = csv2parquet::validate_schema_file(&path)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("is not valid JSON"));
+    assert!(error.contains("line 2"));
+
+    std::fs::write(&path, r#"{"fields": [{"name": "id"}]}"#).unwrap();
+    let error = csv2parquet::validate_schema_file(&path)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("is not a valid Arrow schema"));
+
+    let error = csv2parquet::validate_schema_file(&case.path("missing.json"))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Error opening schema file"));
+}
+
+#[test]
+fn float32_columns() {
+    let case = Case::new("a,b\n1.25,0.1\n3.14159265358979,2\n");
+    case.convert(|opts| opts.float32_columns = vec!["a".to_string()])
+        .unwrap();
+
+    let batch = case.read();
+    assert_eq!(data_types(&case), [DataType::Float32, DataType::Float64]);
+    let a = batch.column(0).as_any();
+    let a = a
+        .downcast_ref::<arrow::array::Float32Array>()
+        .unwrap()
+        .values();
+    assert_eq!(a.to_vec(), [1.25_f32, std::f32::consts::PI]);
+
+    let error = case
+        .convert(|opts| opts.float32_columns = vec!["c".to_string()])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Unknown column \"c\" in float32_columns"));
+}
+
+#[test]
+fn integer_width() {
+    let case = Case::new("small,large\n1,1000\n-2,70000\n");
+    case.convert(|opts| {
+        opts.integer_width = Some(csv2parquet::IntWidth::I32);
+        opts.column_integer_width = vec![("small".to_string(), csv2parquet::IntWidth::I8)];
+    })
+    .unwrap();
+    assert_eq!(data_types(&case), [DataType::Int8, DataType::Int32]);
+    assert_eq!(
+        strings(&case.read(), "small"),
+        opt(&[Some("1"), Some("-2")])
+    );
+
+    let error = case
+        .convert(|opts| opts.integer_width = Some(csv2parquet::IntWidth::I16))
+        .unwrap_err();
+    assert!(error.to_string().contains("parsing value 70000"));
+}
+
+#[test]
+fn unsigned_columns() {
+    let case = Case::new("id,delta\n3,1\n7,-1\n");
+    case.convert(|opts| {
+        opts.unsigned_columns = vec!["id".to_string()];
+        opts.column_integer_width = vec![("id".to_string(), csv2parquet::IntWidth::I32)];
+    })
+    .unwrap();
+    assert_eq!(data_types(&case), [DataType::UInt32, DataType::Int64]);
+    assert_eq!(strings(&case.read(), "id"), opt(&[Some("3"), Some("7")]));
+
+    let error = case
+        .convert(|opts| opts.unsigned_columns = vec!["delta".to_string()])
+        .unwrap_err();
+    assert!(error.to_string().contains("parsing value -1"));
+}
+
+fn nullable(case: &Case) -> Vec<bool> {
+    let schema = case.read().schema();
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.is_nullable())
+        .collect()
+}
+
+#[test]
+fn null_sentinel() {
+    let case = Case::new("name,score\nann,1\n,\n");
+    case.convert(|opts| opts.null_sentinel = Some("N/A".to_string()))
+        .unwrap();
+    let batch = case.read();
+    assert_eq!(strings(&batch, "name"), opt(&[Some("ann"), Some("N/A")]));
+    // The sentinel is no number, so the numeric column keeps its nulls.
+    assert_eq!(strings(&batch, "score"), opt(&[Some("1"), None]));
+    assert_eq!(nullable(&case), [false, true]);
+
+    case.convert(|opts| opts.null_sentinel = Some("-1".to_string()))
+        .unwrap();
+    let batch = case.read();
+    assert_eq!(strings(&batch, "name"), opt(&[Some("ann"), Some("-1")]));
+    assert_eq!(strings(&batch, "score"), opt(&[Some("1"), Some("-1")]));
+    assert_eq!(nullable(&case), [false, false]);
+}
+
+#[test]
+fn column_defaults() {
+    let case = Case::new("name,score,note\nann,1,a\n,,\n");
+    case.convert(|opts| {
+        opts.column_defaults = vec![("score".to_string(), "0".to_string())];
+        opts.null_sentinel = Some("N/A".to_string());
+    })
+    .unwrap();
+    let batch = case.read();
+    assert_eq!(strings(&batch, "score"), opt(&[Some("1"), Some("0")]));
+    assert_eq!(strings(&batch, "note"), opt(&[Some("a"), Some("N/A")]));
+
+    case.convert(|opts| opts.column_defaults = vec![("score".to_string(), "0".to_string())])
+        .unwrap();
+    let batch = case.read();
+    assert_eq!(strings(&batch, "score"), opt(&[Some("1"), Some("0")]));
+    assert_eq!(strings(&batch, "name"), opt(&[Some("ann"), None]));
+    assert_eq!(nullable(&case), [true, false, true]);
+
+    let error = case
+        .convert(|opts| opts.column_defaults = vec![("score".to_string(), "zero".to_string())])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Default \"zero\" is not a valid Int64"));
+}
+
+#[test]
+fn type_priority() {
+    let case = Case::new("day,flag,n\n2024-01-02,true,1\n2024-01-03,false,2\n");
+    let priority = |types: &[&str]| {
+        case.convert(|opts| {
+            opts.type_priority = Some(types.iter().map(|name| name.to_string()).collect())
+        })
+        .unwrap();
+        data_types(&case)
+    };
+
+    // Without dates and booleans, those columns stay strings.
+    assert_eq!(
+        priority(&["integer", "float", "string"]),
+        [DataType::Utf8, DataType::Utf8, DataType::Int64]
+    );
+    // Floats before integers take the integer column.
+    assert_eq!(
+        priority(&["boolean", "float", "integer", "date", "string"]),
+        [DataType::Date32, DataType::Boolean, DataType::Float64]
+    );
+
+    let error = case
+        .convert(|opts| opts.type_priority = Some(vec!["decimal".to_string()]))
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Invalid type priority \"decimal\""));
+}
+
+#[test]
+fn physical_types() {
+    use parquet::basic::Type;
+
+    let case = Case::new("id,score,name\n1,1.5,a\n2,2.5,b\n");
+    let physical_types = |case: &Case| {
+        let metadata = case.metadata();
+        let descriptor = metadata.file_metadata().schema_descr();
+        descriptor
+            .columns()
+            .iter()
+            .map(|column| column.physical_type())
+            .collect::<Vec<_>>()
+    };
+    case.convert(|_| {}).unwrap();
+    assert_eq!(
+        physical_types(&case),
+        [Type::INT64, Type::DOUBLE, Type::BYTE_ARRAY]
+    );
+
+    case.convert(|opts| {
+        opts.physical_types = vec![
+            ("id".to_string(), "int32".to_string()),
+            ("score".to_string(), "BYTE_ARRAY".to_string()),
+        ]
+    })
+    .unwrap();
+    assert_eq!(
+        physical_types(&case),
+        [Type::INT32, Type::BYTE_ARRAY, Type::BYTE_ARRAY]
+    );
+    assert_eq!(
+        strings(&case.read(), "score"),
+        opt(&[Some("1.5"), Some("2.5")])
+    );
+
+    let error = case
+        .convert(|opts| opts.physical_types = vec![("name".to_string(), "INT32".to_string())])
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Cannot write column \"name\" of type Utf8 as parquet physical type INT32"));
+}
+
+#[test]
+fn fail_on_all_string() {
+    // Split on `,` instead of `;`, every column mixes numbers with the real delimiter.
+    let case = Case::new("a;b,c;d\n1;2,3;4\n5;6,7;8\n");
+    let error = case
+        .convert(|opts| opts.fail_on_all_string = true)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Inferred all 2 columns as strings"));
+
+    case.convert(|opts| {
+        opts.fail_on_all_string = true;
+        opts.delimiter = ';';
+    })
+    .unwrap();
+    assert_eq!(
+        data_types(&case),
+        [DataType::Int64, DataType::Utf8, DataType::Int64]
+    );
+
+    // A single string column is not taken as a sign of a wrong setting.
+    let case = Case::new("name\nx\ny\n");
+    case.convert(|opts| opts.fail_on_all_string = true).unwrap();
+}
+
+#[test]
+fn match_schema_to_header() {
+    let case = Case::new("b,a\nx,1\ny,2\n");
+    let schema = |opts: &mut csv2parquet::Opts| {
+        opts.schema_spec = Some("a:int64,b:string".to_string());
+    };
+
+    // By position, `a` gets the strings of the first CSV column.
+    assert!(case.convert(schema).is_err());
+
+    case.convert(|opts| {
+        schema(opts);
+        opts.match_schema_to_header = true;
+    })
+    .unwrap();
+    let batch = case.read();
+    assert_eq!(column_names(&batch), ["b", "a"]);
+    assert_eq!(data_types(&case), [DataType::Utf8, DataType::Int64]);
+    assert_eq!(strings(&batch, "a"), opt(&[Some("1"), Some("2")]));
+    assert_eq!(strings(&batch, "b"), opt(&[Some("x"), Some("y")]));
+
+    let error = case
+        .convert(|opts| {
+            schema(opts);
+            opts.match_schema_to_header = true;
+            opts.reorder_to_schema = true;
+        })
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("cannot be combined with reorder_to_schema"));
+}
+
+#[test]
+fn baseline_schema() {
+    let baseline = Case::new("id,price,name\n1,2.5,a\n");
+    let baseline_path = baseline.path("baseline.json");
+    baseline
+        .convert(|opts| opts.extract_schema = Some(baseline_path.clone()))
+        .unwrap();
+
+    let same = Case::new("id,price,name\n2,3.5,b\n");
+    same.convert(|opts| {
+        opts.baseline_schema = Some(baseline_path.clone());
+        opts.fail_on_schema_drift = true;
+    })
+    .unwrap();
+
+    // `price` now holds strings and `name` was replaced by `label`.
+    let drifted = Case::new("id,price,label\n2,n/a,b\n");
+    drifted
+        .convert(|opts| opts.baseline_schema = Some(baseline_path.clone()))
+        .unwrap();
+    assert_eq!(
+        data_types(&drifted),
+        [DataType::Int64, DataType::Utf8, DataType::Utf8]
+    );
+
+    let error = drifted
+        .convert(|opts| {
+            opts.baseline_schema = Some(baseline_path.clone());
+            opts.fail_on_schema_drift = true;
+        })
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("The schema differs from the baseline"));
+    assert!(error.contains("column \"name\" was removed"));
+    assert!(error.contains("column \"price\" changed from Float64 to Utf8"));
+    assert!(error.contains("column \"label\" was added as Utf8"));
+}
@@ -0,0 +1,272 @@
+use arrow::{
+    array::{AsArray, RecordBatch, UInt32Array},
+    compute::{cast, concat_batches, lexsort_to_indices, take_record_batch, SortColumn},
+    datatypes::{DataType, Schema, SchemaRef},
+};
+use arrow_schema::SortOptions;
+use arrow_tools::parquet_writer::WriterArgs;
+use clap::{Parser, ValueHint};
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, arrow_to_parquet_schema, ArrowWriter},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+    format::SortingColumn,
+};
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// The directory name Hive-style readers use for the partition of null values.
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+#[derive(Parser)]
+#[clap(version = env!("CARGO_PKG_VERSION"), author = "Dominik Moritz <domoritz@cmu.edu>")]
+pub struct Opts {
+    /// Input Parquet files. All files must have the same schema.
+    #[clap(name = "INPUT", value_parser, value_hint = ValueHint::AnyPath, required = true, num_args = 1..)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Output file, or directory with --partition-by.
+    #[clap(name = "PARQUET", value_parser, value_hint = ValueHint::AnyPath)]
+    pub output: PathBuf,
+
+    #[clap(flatten)]
+    pub writer: WriterArgs,
+
+    /// Sort the rows of all inputs by these columns, the first one first. Reads all rows into memory.
+    #[clap(long, value_delimiter = ',')]
+    pub sort_by: Vec<String>,
+
+    /// Sort in descending instead of ascending order, with nulls first either way.
+    #[clap(long, requires = "sort_by")]
+    pub descending: bool,
+
+    /// Write one file per value of this column, as `<column>=<value>/part-0.parquet` in the output
+    /// directory. The files keep the column.
+    #[clap(long)]
+    pub partition_by: Option<String>,
+
+    /// Print the schema to stderr.
+    #[clap(short, long)]
+    pub print_schema: bool,
+
+    /// Only print the schema
+    #[clap(short = 'n', long)]
+    pub dry: bool,
+}
+
+fn open_input(path: &Path) -> Result<ParquetRecordBatchReaderBuilder<File>, ParquetError> {
+    let file = File::open(path).map_err(|error| {
+        ParquetError::General(format!(
+            "Error opening input file: {path:?}, message: {error}"
+        ))
+    })?;
+    ParquetRecordBatchReaderBuilder::try_new(file)
+}
+
+/// Checks that an input has the same fields as the first input. Schema-level
+/// metadata is allowed to differ; the output keeps the metadata of the first file.
+fn check_compatible(
+    expected: &Schema,
+    actual: &Schema,
+    path: &PathBuf,
+) -> Result<(), ParquetError> {
+    if expected.fields() != actual.fields() {
+        return Err(ParquetError::General(format!(
+            "Schema of {path:?} does not match the schema of the first input.\nExpected: {expected}\nFound: {actual}"
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the index of the column named `name`, failing with `option` in the message otherwise.
+fn column_index(schema: &Schema, name: &str, option: &str) -> Result<usize, ParquetError> {
+    schema
+        .index_of(name)
+        .map_err(|_| ParquetError::General(format!("Unknown column {name:?} in {option}")))
+}
+
+/// Describes the sort order of the output for its metadata. Parquet refers to columns by leaf
+/// index, which differs from the field index after nested columns.
+fn sorting_columns(
+    schema: &Schema,
+    columns: &[usize],
+    descending: bool,
+) -> Result<Vec<SortingColumn>, ParquetError> {
+    let descriptor = arrow_to_parquet_schema(schema)?;
+    columns
+        .iter()
+        .map(|&column| {
+            let name = schema.field(column).name();
+            descriptor
+                .columns()
+                .iter()
+                .position(|leaf| leaf.path().parts() == [name.as_str()])
+                .map(|leaf| SortingColumn::new(leaf as i32, descending, true))
+                .ok_or_else(|| {
+                    ParquetError::General(format!("Cannot sort by the nested column {name:?}"))
+                })
+        })
+        .collect()
+}
+
+/// Sorts the rows of all batches together by `columns`.
+fn sort_batches(
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+    columns: &[usize],
+    descending: bool,
+) -> Result<RecordBatch, ParquetError> {
+    let batch = concat_batches(schema, batches)?;
+    let options = SortOptions {
+        descending,
+        nulls_first: true,
+    };
+    let sort_columns: Vec<SortColumn> = columns
+        .iter()
+        .map(|&column| SortColumn {
+            values: batch.column(column).clone(),
+            options: Some(options),
+        })
+        .collect();
+    let indices = lexsort_to_indices(&sort_columns, None)
+        .map_err(|error| ParquetError::General(format!("Error sorting the rows: {error}")))?;
+    Ok(take_record_batch(&batch, &indices)?)
+}
+
+/// The directory name of a partition value, escaping characters that are not safe in paths like
+/// Hive does.
+fn partition_name(value: Option<&str>) -> String {
+    let Some(value) = value else {
+        return NULL_PARTITION.to_string();
+    };
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | ' ' => c.to_string(),
+            _ => {
+                let mut bytes = [0; 4];
+                c.encode_utf8(&mut bytes)
+                    .bytes()
+                    .map(|byte| format!("%{byte:02X}"))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+/// Writes the rows of every value of `column` to their own file in `dir`.
+fn write_partitions(
+    dir: &Path,
+    schema: &SchemaRef,
+    batches: impl Iterator<Item = Result<RecordBatch, ParquetError>>,
+    column: usize,
+    props: WriterProperties,
+) -> Result<(), ParquetError> {
+    let name = schema.field(column).name();
+    let mut writers: BTreeMap<String, ArrowWriter<File>> = BTreeMap::new();
+
+    for batch in batches {
+        let batch = batch?;
+        let values = cast(batch.column(column), &DataType::Utf8).map_err(|error| {
+            ParquetError::General(format!("Cannot partition by column {name:?}: {error}"))
+        })?;
+        let mut partitions: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        for (row, value) in values.as_string::<i32>().iter().enumerate() {
+            partitions
+                .entry(partition_name(value))
+                .or_default()
+                .push(row as u32);
+        }
+
+        for (value, rows) in partitions {
+            let writer = match writers.entry(value) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let partition = dir.join(format!("{name}={}", entry.key()));
+                    fs::create_dir_all(&partition)?;
+                    let output = File::create(partition.join("part-0.parquet"))?;
+                    entry.insert(ArrowWriter::try_new(
+                        output,
+                        schema.clone(),
+                        Some(props.clone()),
+                    )?)
+                }
+            };
+            writer.write(&take_record_batch(&batch, &UInt32Array::from(rows))?)?;
+        }
+    }
+
+    for writer in writers.into_values() {
+        writer.close()?;
+    }
+    Ok(())
+}
+
+/// Merges the inputs into the output, sorting and partitioning the rows if requested.
+pub fn convert(opts: Opts) -> Result<(), ParquetError> {
+    let mut builders = Vec::with_capacity(opts.inputs.len());
+    for path in &opts.inputs {
+        builders.push((path, open_input(path)?));
+    }
+
+    let schema: SchemaRef = builders[0].1.schema().clone();
+    for (path, builder) in &builders[1..] {
+        check_compatible(&schema, builder.schema(), path)?;
+    }
+
+    if opts.print_schema || opts.dry {
+        let json = serde_json::to_string_pretty(&schema).unwrap();
+        eprintln!("Schema:");
+        println!("{json}");
+        if opts.dry {
+            return Ok(());
+        }
+    }
+
+    let sort_by = opts
+        .sort_by
+        .iter()
+        .map(|name| column_index(&schema, name, "sort_by"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let partition_by = opts
+        .partition_by
+        .as_deref()
+        .map(|name| column_index(&schema, name, "partition_by"))
+        .transpose()?;
+
+    let mut props = opts.writer.writer_properties_builder();
+    if !sort_by.is_empty() {
+        props =
+            props.set_sorting_columns(Some(sorting_columns(&schema, &sort_by, opts.descending)?));
+    }
+    let props = props.build();
+
+    let mut batches: Box<dyn Iterator<Item = Result<RecordBatch, ParquetError>>> = Box::new(
+        builders
+            .into_iter()
+            .map(|(_, builder)| builder.build())
+            .flat_map(|reader| match reader {
+                Ok(reader) => Box::new(reader.map(|batch| batch.map_err(ParquetError::from)))
+                    as Box<dyn Iterator<Item = _>>,
+                Err(error) => Box::new(std::iter::once(Err(error))),
+            }),
+    );
+    if !sort_by.is_empty() {
+        let all = batches.collect::<Result<Vec<_>, _>>()?;
+        let sorted = sort_batches(&schema, &all, &sort_by, opts.descending)?;
+        batches = Box::new(std::iter::once(Ok(sorted)));
+    }
+
+    if let Some(column) = partition_by {
+        fs::create_dir_all(&opts.output)?;
+        return write_partitions(&opts.output, &schema, batches, column, props);
+    }
+
+    let output = File::create(&opts.output)?;
+    let mut writer = ArrowWriter::try_new(output, schema, Some(props))?;
+    for batch in batches {
+        writer.write(&batch?)?;
+    }
+    writer.close().map(|_| ())
+}
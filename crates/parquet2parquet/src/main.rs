@@ -0,0 +1,7 @@
+use clap::Parser;
+use parquet::errors::ParquetError;
+use parquet2parquet::{convert, Opts};
+
+fn main() -> Result<(), ParquetError> {
+    convert(Opts::parse())
+}
@@ -0,0 +1,198 @@
+use arrow::{
+    array::{Int64Array, RecordBatch, StringArray},
+    compute::concat_batches,
+    datatypes::{DataType, Field, Schema},
+};
+use clap::Parser;
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    basic::Compression,
+    errors::ParquetError,
+    file::{metadata::ParquetMetaData, properties::WriterProperties},
+};
+use parquet2parquet::Opts;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn batch(ids: &[i64], names: &[Option<&str>]) -> RecordBatch {
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(Int64Array::from(ids.to_vec())),
+            Arc::new(StringArray::from(names.to_vec())),
+        ],
+    )
+    .unwrap()
+}
+
+fn write_parquet(path: &Path, batch: &RecordBatch, compression: Compression) {
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(File::create(path).unwrap(), batch.schema(), Some(props)).unwrap();
+    writer.write(batch).unwrap();
+    writer.close().unwrap();
+}
+
+fn read_parquet(path: &Path) -> RecordBatch {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+    let schema = builder.schema().clone();
+    let batches = builder
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    concat_batches(&schema, &batches).unwrap()
+}
+
+fn read_metadata(path: &Path) -> ParquetMetaData {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+    builder.metadata().as_ref().clone()
+}
+
+fn ids(batch: &RecordBatch) -> Vec<i64> {
+    let column = batch.column_by_name("id").unwrap();
+    let column = column.as_any().downcast_ref::<Int64Array>().unwrap();
+    column.values().to_vec()
+}
+
+/// Two inputs written with different codecs, `a.parquet` with ids 3, 1 and `b.parquet` with
+/// ids 2, 4.
+fn inputs() -> (TempDir, PathBuf, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.parquet");
+    let b = dir.path().join("b.parquet");
+    write_parquet(&a, &batch(&[3, 1], &[Some("x"), None]), Compression::SNAPPY);
+    write_parquet(
+        &b,
+        &batch(&[2, 4], &[Some("a/b"), Some("x")]),
+        Compression::UNCOMPRESSED,
+    );
+    (dir, a, b)
+}
+
+fn convert(args: &[&str]) -> Result<(), ParquetError> {
+    let args = std::iter::once("parquet2parquet").chain(args.iter().copied());
+    parquet2parquet::convert(Opts::parse_from(args))
+}
+
+#[test]
+fn merge_and_recompress() {
+    let (dir, a, b) = inputs();
+    let output = dir.path().join("merged.parquet");
+    convert(&[
+        a.to_str().unwrap(),
+        b.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--compression",
+        "zstd",
+    ])
+    .unwrap();
+
+    assert_eq!(ids(&read_parquet(&output)), [3, 1, 2, 4]);
+    let metadata = read_metadata(&output);
+    let codecs: Vec<_> = metadata
+        .row_groups()
+        .iter()
+        .flat_map(|row_group| row_group.columns())
+        .map(|column| column.compression())
+        .collect();
+    assert!(!codecs.is_empty());
+    assert!(codecs
+        .iter()
+        .all(|codec| matches!(codec, Compression::ZSTD(_))));
+}
+
+#[test]
+fn schema_mismatch() {
+    let (dir, a, _) = inputs();
+    let other = dir.path().join("other.parquet");
+    let schema = Schema::new(vec![Field::new("id", DataType::Utf8, false)]);
+    let other_batch = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(StringArray::from(vec!["1"]))],
+    )
+    .unwrap();
+    write_parquet(&other, &other_batch, Compression::SNAPPY);
+
+    let output = dir.path().join("merged.parquet");
+    let error = convert(&[
+        a.to_str().unwrap(),
+        other.to_str().unwrap(),
+        output.to_str().unwrap(),
+    ])
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("does not match the schema of the first input"));
+}
+
+#[test]
+fn sort_by() {
+    let (dir, a, b) = inputs();
+    let output = dir.path().join("sorted.parquet");
+    let sort = |extra: &[&str]| {
+        let mut args = vec![
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+            output.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        convert(&args)
+    };
+
+    sort(&["--sort-by", "id"]).unwrap();
+    assert_eq!(ids(&read_parquet(&output)), [1, 2, 3, 4]);
+    let metadata = read_metadata(&output);
+    let sorting = metadata.row_group(0).sorting_columns().unwrap();
+    assert_eq!(sorting.len(), 1);
+    assert_eq!(sorting[0].column_idx, 0);
+    assert!(!sorting[0].descending);
+
+    // Nulls come first, then ties keep going by the next column, descending.
+    sort(&["--sort-by", "name,id", "--descending"]).unwrap();
+    assert_eq!(ids(&read_parquet(&output)), [1, 4, 3, 2]);
+
+    let error = sort(&["--sort-by", "missing"]).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Unknown column \"missing\" in sort_by"));
+}
+
+#[test]
+fn partition_by() {
+    let (dir, a, b) = inputs();
+    let output = dir.path().join("dataset");
+    convert(&[
+        a.to_str().unwrap(),
+        b.to_str().unwrap(),
+        output.to_str().unwrap(),
+        "--partition-by",
+        "name",
+        "--sort-by",
+        "id",
+    ])
+    .unwrap();
+
+    let mut partitions: Vec<_> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    partitions.sort();
+    assert_eq!(
+        partitions,
+        ["name=__HIVE_DEFAULT_PARTITION__", "name=a%2Fb", "name=x"]
+    );
+
+    let part = |name: &str| read_parquet(&output.join(name).join("part-0.parquet"));
+    assert_eq!(ids(&part("name=x")), [3, 4]);
+    assert_eq!(ids(&part("name=a%2Fb")), [2]);
+    assert_eq!(ids(&part("name=__HIVE_DEFAULT_PARTITION__")), [1]);
+}